@@ -1,5 +1,6 @@
 use crate::value::Value;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum OpCode {
     OpReturn,
@@ -8,6 +9,14 @@ pub enum OpCode {
     OpSubtract,
     OpMultiply,
     OpDivide,
+    OpModulo,
+    OpPower,
+    OpBitAnd,
+    OpBitOr,
+    OpBitXor,
+    OpBitNot,
+    OpShiftLeft,
+    OpShiftRight,
     OpNil,
     OpTrue,
     OpFalse,
@@ -18,6 +27,7 @@ pub enum OpCode {
     OpPrint,
     OpPop,
     OpDefineGlobal { index: usize },
+    OpDefineConstant { index: usize },
     OpGetGlobal { index: usize },
     OpSetGlobal { index: usize },
     OpGetLocal { index: usize },
@@ -25,8 +35,70 @@ pub enum OpCode {
     OpConstant { index: usize },
     OpCall { arg_count: usize },
     OpJumpIfFalse { offset: usize },
+    OpJumpIfNil { offset: usize },
     OpJump { offset: usize },
+    OpJumpIfPassed { index: usize, offset: usize },
     OpJumpBack { offset: usize },
+    OpGetIterator,
+    OpIterate { index: usize },
+    OpJumpIfExhausted { index: usize, offset: usize },
+    OpJumpTable { index: usize },
+    OpTry { offset: usize },
+    OpEndTry,
+    OpThrow,
+    OpAssert { index: usize },
+    OpYield,
+    OpImport { index: usize },
+    OpEndModule,
+    OpClosure { index: usize },
+    OpGetUpvalue { index: usize },
+    OpSetUpvalue { index: usize },
+    OpCloseUpvalue,
+    OpClass { index: usize },
+    OpGetProperty { index: usize },
+    OpSetProperty { index: usize },
+    OpMethod { index: usize },
+    OpInherit,
+    OpGetSuper { index: usize },
+    OpBuildList { item_count: usize },
+    OpBuildMap { entry_count: usize },
+    OpBuildTuple { item_count: usize },
+    OpUnpack { count: usize },
+    OpGetIndex,
+    OpSetIndex,
+    OpGetSlice,
+    OpToString,
+}
+
+/// Jump targets of a match statement whose cases are dense integers
+#[derive(Debug, Clone, PartialEq)]
+pub struct JumpTable {
+    /// The value which the first offset is for
+    pub min: i64,
+    /// Offsets from the instruction after OpJumpTable
+    pub offsets: Vec<usize>,
+    /// Offset for values which no case matches
+    pub default_offset: usize,
+}
+
+impl JumpTable {
+    pub fn new() -> JumpTable {
+        JumpTable {
+            min: 0,
+            offsets: Vec::new(),
+            default_offset: 0,
+        }
+    }
+
+    pub fn offset(&self, value: &Value) -> usize {
+        value
+            .as_integer()
+            .and_then(|integer| integer.checked_sub(self.min))
+            .and_then(|index| usize::try_from(index).ok())
+            .and_then(|index| self.offsets.get(index))
+            .copied()
+            .unwrap_or(self.default_offset)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -34,6 +106,7 @@ pub struct Chunk {
     pub code: Vec<OpCode>,
     pub constants: Vec<Value>,
     pub lines: Vec<usize>,
+    pub jump_tables: Vec<JumpTable>,
 }
 
 impl Chunk {
@@ -42,6 +115,7 @@ impl Chunk {
             code: Vec::new(),
             constants: Vec::new(),
             lines: Vec::new(),
+            jump_tables: Vec::new(),
         }
     }
 
@@ -54,6 +128,11 @@ impl Chunk {
         self.constants.push(value);
         self.constants.len() - 1 // index of value in constants
     }
+
+    pub fn add_jump_table(&mut self, jump_table: JumpTable) -> usize {
+        self.jump_tables.push(jump_table);
+        self.jump_tables.len() - 1
+    }
 }
 
 #[cfg(test)]
@@ -84,4 +163,20 @@ mod tests {
         assert_eq!(index1, 0);
         assert_eq!(index2, 1);
     }
+
+    #[test]
+    fn test_jump_table_offset() {
+        let jump_table = JumpTable {
+            min: -1,
+            offsets: vec![3, 5, 7],
+            default_offset: 9,
+        };
+        assert_eq!(jump_table.offset(&Value::Integer(-1)), 3);
+        assert_eq!(jump_table.offset(&Value::Integer(1)), 7);
+        assert_eq!(jump_table.offset(&Value::Number(0.0)), 5);
+        assert_eq!(jump_table.offset(&Value::Number(0.5)), 9);
+        assert_eq!(jump_table.offset(&Value::Integer(2)), 9);
+        assert_eq!(jump_table.offset(&Value::Integer(i64::MIN)), 9);
+        assert_eq!(jump_table.offset(&Value::Bool(true)), 9);
+    }
 }
@@ -2,13 +2,15 @@ mod error_report;
 mod parser;
 mod precedence;
 
+use std::rc::Rc;
+
 use crate::{
-    chunk::{Chunk, OpCode},
+    chunk::{Chunk, JumpTable, OpCode},
     disassembler,
     scan::Source,
-    token::Token,
+    token::{Token, TokenType},
     value::{
-        object::{Obj, ObjFunction},
+        object::{Obj, ObjFunction, UpvalueIndex},
         Value,
     },
     InterpretError,
@@ -36,12 +38,94 @@ impl Env {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum FunctionType {
     Function,
+    Method,
+    Initializer,
     Script,
 }
 
+#[derive(Clone, Debug)]
+struct Loop {
+    /// Address where `continue` jumps back to
+    continue_target: usize,
+    /// Env's scope_depth outside of the loop body.
+    /// Locals deeper than this are discarded when leaving the loop early.
+    scope_depth: usize,
+    /// Jumps emitted by `break` which are patched when the loop ends
+    break_jumps: Vec<usize>,
+    /// Number of try statements outside of the loop.
+    /// Leaving the loop early runs the finally blocks of deeper try statements first.
+    try_depth: usize,
+}
+
+impl Loop {
+    fn new(continue_target: usize, scope_depth: usize, try_depth: usize) -> Loop {
+        Loop {
+            continue_target,
+            scope_depth,
+            break_jumps: Vec::new(),
+            try_depth,
+        }
+    }
+}
+
+/// How the try and catch blocks of a try statement were left,
+/// which is carried out after the finally block runs
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Completion {
+    Normal,
+    Throw,
+    Return,
+    Break,
+    Continue,
+}
+
+const COMPLETION_COUNT: usize = 5;
+
+/// A try statement whose try or catch block is being compiled.
+/// Every way out of them goes through the finally block, which may be empty,
+/// with the completion and its value stored in two hidden locals.
+#[derive(Clone, Debug)]
+struct TryBlock {
+    /// Env's scope_depth of the hidden locals
+    scope_depth: usize,
+    /// Hidden local holding the exception or the return value while the finally block runs
+    value_slot: usize,
+    /// Hidden local holding the completion to carry out after the finally block
+    completion_slot: usize,
+    /// Jumps to the finally block which are patched when it starts
+    finally_jumps: Vec<usize>,
+    /// Completions of `return`, `break` and `continue` which leave the blocks early
+    early_exits: Vec<Completion>,
+}
+
+impl TryBlock {
+    fn new(scope_depth: usize, value_slot: usize, completion_slot: usize) -> TryBlock {
+        TryBlock {
+            scope_depth,
+            value_slot,
+            completion_slot,
+            finally_jumps: Vec::new(),
+            early_exits: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct ClassCompiler {
+    has_superclass: bool,
+}
+
+impl ClassCompiler {
+    fn new() -> ClassCompiler {
+        ClassCompiler {
+            has_superclass: false,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 struct Local {
     name: Token,
@@ -51,6 +135,11 @@ struct Local {
     // it holds whether it's in initialized state or not.
     // Check out sample/self_reference_variable.lox to see the example.
     initialized: bool,
+    /// Whether a closure captures this variable.
+    /// Captured variables must be moved off the stack when they go out of scope.
+    is_captured: bool,
+    /// Declared with `const`, so it can't be assigned
+    is_const: bool,
 }
 
 impl Local {
@@ -59,6 +148,8 @@ impl Local {
             name,
             depth,
             initialized: false,
+            is_captured: false,
+            is_const: false,
         }
     }
 }
@@ -68,15 +159,40 @@ pub struct Compiler {
     env: Env,
     pub function: ObjFunction,
     function_type: FunctionType,
+    /// Loops enclosing the code being compiled, innermost last
+    loops: Vec<Loop>,
+    /// Try blocks enclosing the code being compiled, innermost last
+    try_blocks: Vec<TryBlock>,
 }
 
 impl Compiler {
     fn new(function_type: FunctionType) -> Compiler {
-        Compiler {
+        let mut function = ObjFunction::new();
+        function.script = function_type == FunctionType::Script;
+        let mut compiler = Compiler {
             env: Env::new(),
-            function: ObjFunction::new(),
+            function,
             function_type,
-        }
+            loops: Vec::new(),
+            try_blocks: Vec::new(),
+        };
+        compiler.reserve_first_slot();
+        compiler
+    }
+
+    /// Slot 0 holds the function being called.
+    /// In methods, the receiver is stored there instead so that `this` can be
+    /// resolved as an ordinary local variable.
+    fn reserve_first_slot(&mut self) {
+        let name = match self.function_type {
+            FunctionType::Method | FunctionType::Initializer => "this",
+            FunctionType::Function | FunctionType::Script => "",
+        };
+        let token = Token::new(TokenType::This, name.to_string(), 0);
+        let mut local = Local::new(token, 0);
+        local.initialized = true;
+        self.env.locals.push(local);
+        self.env.local_count += 1;
     }
 
     fn add_local(&mut self, token: Token) {
@@ -85,13 +201,21 @@ impl Compiler {
         self.env.local_count += 1
     }
 
+    /// Declare a local for a value the compiler keeps on the stack,
+    /// which scripts can't refer to by name. Returns its stack slot.
+    fn add_hidden_local(&mut self) -> usize {
+        self.add_local(Token::new(TokenType::Identifier, String::new(), 0));
+        self.mark_initialized();
+        self.env.local_count - 1
+    }
+
     fn mark_initialized(&mut self) {
         // other than local variable
         if self.env.scope_depth == 0 {
             return;
         }
         // local variable
-        let mut initialized_local_variable = &mut self.env.locals[self.env.local_count - 1];
+        let initialized_local_variable = &mut self.env.locals[self.env.local_count - 1];
         initialized_local_variable.initialized = true;
     }
 
@@ -104,11 +228,127 @@ impl Compiler {
         while self.env.local_count > 0
             && self.env.locals[self.env.local_count - 1].depth > self.env.scope_depth
         {
-            self.emit_byte(OpCode::OpPop, line);
+            let local = self.env.locals.pop().unwrap();
+            if local.is_captured {
+                self.emit_byte(OpCode::OpCloseUpvalue, line);
+            } else {
+                self.emit_byte(OpCode::OpPop, line);
+            }
             self.env.local_count -= 1
         }
     }
 
+    /// Emit instructions to discard the locals deeper than `depth`
+    /// without removing them from the compiler, e.g. when jumping out of a loop.
+    fn discard_locals(&mut self, depth: usize, line: usize) {
+        let captured: Vec<bool> = self
+            .env
+            .locals
+            .iter()
+            .rev()
+            .take_while(|local| local.depth > depth)
+            .map(|local| local.is_captured)
+            .collect();
+        for is_captured in captured {
+            if is_captured {
+                self.emit_byte(OpCode::OpCloseUpvalue, line);
+            } else {
+                self.emit_byte(OpCode::OpPop, line);
+            }
+        }
+    }
+
+    fn begin_loop(&mut self, continue_target: usize) {
+        let new_loop = Loop::new(continue_target, self.env.scope_depth, self.try_blocks.len());
+        self.loops.push(new_loop);
+    }
+
+    /// Patch the jumps of `break` to the current address
+    fn end_loop(&mut self) {
+        let ended_loop = self.loops.pop().unwrap();
+        for jump in ended_loop.break_jumps {
+            self.patch_jump(jump);
+        }
+    }
+
+    /// Emit instructions for `return`, `break` or `continue`.
+    /// The value to return is on top of the stack for `return`.
+    /// If a try statement is left, its finally block runs first and then the completion
+    /// is carried out again from the try statement, which may leave outer ones.
+    fn emit_exit(&mut self, completion: Completion, line: usize) {
+        let try_depth = match completion {
+            Completion::Return => 0,
+            _ => self.loops.last().unwrap().try_depth,
+        };
+        if self.try_blocks.len() > try_depth {
+            self.emit_byte(OpCode::OpEndTry, line);
+            let try_block = self.try_blocks.last().unwrap();
+            let (value_slot, scope_depth) = (try_block.value_slot, try_block.scope_depth);
+            if completion == Completion::Return {
+                self.emit_byte(OpCode::OpSetLocal { index: value_slot }, line);
+                self.emit_pop(line);
+            }
+            self.discard_locals(scope_depth, line);
+            self.jump_to_finally(completion, line);
+            let early_exits = &mut self.try_blocks.last_mut().unwrap().early_exits;
+            if !early_exits.contains(&completion) {
+                early_exits.push(completion);
+            }
+            return;
+        }
+
+        match completion {
+            Completion::Return => self.emit_byte(OpCode::OpReturn, line),
+            Completion::Break => {
+                let depth = self.loops.last().unwrap().scope_depth;
+                self.discard_locals(depth, line);
+                let jump = self.emit_jump(OpCode::OpJump { offset: 0 }, line);
+                self.loops.last_mut().unwrap().break_jumps.push(jump);
+            }
+            Completion::Continue => {
+                let current_loop = self.loops.last().unwrap();
+                let (depth, continue_target) =
+                    (current_loop.scope_depth, current_loop.continue_target);
+                self.discard_locals(depth, line);
+                self.emit_jump_back(continue_target, line);
+            }
+            Completion::Normal | Completion::Throw => panic!("Expected early exit"),
+        }
+    }
+
+    /// Store the completion in the innermost try statement
+    fn set_completion(&mut self, completion: Completion, line: usize) {
+        let index = self.try_blocks.last().unwrap().completion_slot;
+        self.emit_constant(Value::Integer(completion as i64), line);
+        self.emit_byte(OpCode::OpSetLocal { index }, line);
+        self.emit_pop(line);
+    }
+
+    /// Jump to the finally block of the innermost try statement with the completion.
+    /// Only the hidden locals of the try statement may be left on the stack.
+    fn jump_to_finally(&mut self, completion: Completion, line: usize) {
+        self.set_completion(completion, line);
+        let jump = self.emit_jump(OpCode::OpJump { offset: 0 }, line);
+        self.try_blocks.last_mut().unwrap().finally_jumps.push(jump);
+    }
+
+    fn current_code_address(&self) -> usize {
+        self.current_chunk_as_ref().code.len()
+    }
+
+    fn current_constant_count(&self) -> usize {
+        self.current_chunk_as_ref().constants.len()
+    }
+
+    /// Remove the instructions emitted since the address
+    /// along with the constants added after the first `constant_count` ones
+    fn remove_code_since(&mut self, address: usize, constant_count: usize) {
+        let chunk = self.current_chunk_as_mut();
+        chunk.code.truncate(address);
+        chunk.lines.truncate(address);
+        chunk.constants.truncate(constant_count);
+    }
+
     fn current_chunk_as_mut(&mut self) -> &mut Chunk {
         &mut self.function.chunk
     }
@@ -144,24 +384,41 @@ impl Compiler {
         chunk.add_constant(Value::LString(name))
     }
 
+    /// Returns the stack slot of the local variable.
     fn resolve_local(&mut self, name: &Token) -> Result<Option<usize>, InterpretError> {
-        let locals_len = self.env.locals.len();
-        for (i, local) in self.env.locals.iter().rev().enumerate() {
+        for (i, local) in self.env.locals.iter().enumerate().rev() {
             if name.lexeme == local.name.lexeme {
                 if !local.initialized {
                     error_report::report_error(
-                        &name,
+                        name,
                         "Can't read local variable in own initializer",
                     );
                     return Err(InterpretError::CompileError);
                 }
-                return Ok(Some(locals_len - i));
+                return Ok(Some(i));
             }
         }
 
         Ok(None)
     }
 
+    /// Mark the local variable in the given stack slot as captured by a closure.
+    fn capture_local(&mut self, slot: usize) {
+        self.env.locals[slot].is_captured = true;
+    }
+
+    /// Returns the index of the upvalue in the function's upvalue list.
+    /// If the same variable is already captured, the existing upvalue is reused.
+    fn add_upvalue(&mut self, index: usize, is_local: bool) -> usize {
+        let upvalue = UpvalueIndex { index, is_local };
+        let upvalues = &mut self.function.upvalues;
+        if let Some(i) = upvalues.iter().position(|existing| *existing == upvalue) {
+            return i;
+        }
+        upvalues.push(upvalue);
+        upvalues.len() - 1
+    }
+
     fn declare_variable(&mut self, name: &Token) -> Result<(), InterpretError> {
         if !self.is_local() {
             return Ok(());
@@ -180,6 +437,34 @@ impl Compiler {
         self.emit_byte(OpCode::OpDefineGlobal { index: global }, line);
     }
 
+    fn define_constant(&mut self, global: usize, line: usize) {
+        if self.is_local() {
+            self.env.locals[self.env.local_count - 1].is_const = true;
+            self.define_local_variable();
+        } else {
+            self.emit_byte(OpCode::OpDefineConstant { index: global }, line);
+        }
+    }
+
+    fn is_const_local(&self, slot: usize) -> bool {
+        self.env.locals[slot].is_const
+    }
+
+    /// Define the variables of a destructuring declaration, whose values are on the stack in order
+    fn define_variables(&mut self, globals: &[usize], line: usize) {
+        if self.is_local() {
+            let first = self.env.local_count - globals.len();
+            for local in &mut self.env.locals[first..] {
+                local.initialized = true;
+            }
+        } else {
+            // the value of the last variable is on top of the stack
+            for global in globals.iter().rev() {
+                self.define_global_variable(*global, line);
+            }
+        }
+    }
+
     fn define_variable(&mut self, global: usize, line: usize) {
         if self.is_local() {
             self.define_local_variable();
@@ -196,11 +481,47 @@ impl Compiler {
         let target = code[jump_start].clone();
         code[jump_start] = match target {
             OpCode::OpJumpIfFalse { .. } => OpCode::OpJumpIfFalse { offset },
+            OpCode::OpJumpIfNil { .. } => OpCode::OpJumpIfNil { offset },
             OpCode::OpJump { .. } => OpCode::OpJump { offset },
+            OpCode::OpTry { .. } => OpCode::OpTry { offset },
+            OpCode::OpJumpIfPassed { index, .. } => OpCode::OpJumpIfPassed { index, offset },
+            OpCode::OpJumpIfExhausted { index, .. } => OpCode::OpJumpIfExhausted { index, offset },
             _ => panic!("Expected jump op code"),
         }
     }
 
+    /// Returns the address of OpJumpTable to fill in its targets later
+    fn emit_jump_table(&mut self, line: usize) -> usize {
+        let index = self.current_chunk_as_mut().add_jump_table(JumpTable::new());
+        self.emit_jump(OpCode::OpJumpTable { index }, line)
+    }
+
+    /// Fill in the jump table with the addresses of the case bodies.
+    /// targets[i] is the address for the value min + i, or None to jump to default_target.
+    fn patch_jump_table(
+        &mut self,
+        jump_table_start: usize,
+        min: i64,
+        targets: &[Option<usize>],
+        default_target: usize,
+    ) {
+        let index = match self.current_chunk_as_ref().code[jump_table_start] {
+            OpCode::OpJumpTable { index } => index,
+            _ => panic!("Expected jump table op code"),
+        };
+        let after_jump_table = jump_table_start + 1;
+        let default_offset = default_target - after_jump_table;
+        let offsets = targets
+            .iter()
+            .map(|target| target.map_or(default_offset, |target| target - after_jump_table))
+            .collect();
+        self.current_chunk_as_mut().jump_tables[index] = JumpTable {
+            min,
+            offsets,
+            default_offset,
+        };
+    }
+
     fn emit_byte(&mut self, byte: OpCode, line: usize) {
         self.current_chunk_as_mut().add_code(byte, line)
     }
@@ -216,9 +537,11 @@ impl Compiler {
         self.current_chunk_as_ref().code.len() - 1
     }
 
+    /// jump_back_address is the address of the instruction to be executed next
     fn emit_jump_back(&mut self, jump_back_address: usize, line: usize) {
         let code_size = self.current_chunk_as_ref().code.len();
-        let offset = code_size - jump_back_address;
+        // ip already points to the instruction after OpJumpBack when jumping
+        let offset = code_size + 1 - jump_back_address;
         self.emit_byte(OpCode::OpJumpBack { offset }, line);
     }
 
@@ -226,19 +549,36 @@ impl Compiler {
         self.emit_byte(OpCode::OpPop, line);
     }
 
-    fn end_compiler(&mut self, line: usize) -> ObjFunction {
-        self.emit_byte(OpCode::OpNil, line);
+    /// Initializers implicitly return the instance in slot 0,
+    /// scripts return their module so that it can be imported,
+    /// and other functions return nil.
+    fn emit_return(&mut self, line: usize) {
+        self.emit_implicit_return_value(line);
         self.emit_byte(OpCode::OpReturn, line);
+    }
+
+    fn emit_implicit_return_value(&mut self, line: usize) {
+        match self.function_type {
+            FunctionType::Initializer => self.emit_byte(OpCode::OpGetLocal { index: 0 }, line),
+            FunctionType::Script => self.emit_byte(OpCode::OpEndModule, line),
+            FunctionType::Function | FunctionType::Method => self.emit_byte(OpCode::OpNil, line),
+        }
+    }
+
+    fn end_compiler(&mut self, line: usize) -> ObjFunction {
+        self.emit_return(line);
         self.function.clone()
     }
 }
 
-pub fn compile(source: &str) -> Result<ObjFunction, InterpretError> {
+/// Assert statements are compiled to nothing if strip_asserts is true
+pub fn compile(source: &str, strip_asserts: bool) -> Result<ObjFunction, InterpretError> {
     let source = Source::new(source.to_string());
     let mut root_compiler = Compiler::new(FunctionType::Script);
-    let function = Obj::Function(root_compiler.function.clone());
+    let function = Obj::Function(Rc::new(root_compiler.function.clone()));
     root_compiler.emit_constant(Value::Obj(function), 0);
     let mut parser = Parser::new(source, root_compiler);
+    parser.strip_asserts = strip_asserts;
     let mut compiler = parser.parse()?;
     let function = compiler.end_compiler(parser.previous.unwrap().line);
     if DEBUG {
@@ -1,3 +1,5 @@
+use std::rc::Rc;
+
 use crate::{
     chunk::OpCode,
     scan::{self, Source},
@@ -9,15 +11,24 @@ use crate::{
 use super::{
     error_report,
     precedence::{self, ParseFn, Precedence},
-    Compiler, FunctionType,
+    ClassCompiler, Compiler, Completion, FunctionType, TryBlock, COMPLETION_COUNT,
 };
 
+/// Name of functions created by function expressions
+const ANONYMOUS_FUNCTION_NAME: &str = "anonymous";
+/// Match statements with fewer integer patterns than this are compiled to comparisons
+const JUMP_TABLE_MIN_PATTERNS: usize = 3;
+
 pub struct Parser {
     current: Option<Token>,
     pub previous: Option<Token>,
     source: Source,
     compiler: Compiler,
     enclosing: Vec<Compiler>,
+    /// Class declarations the parser is currently inside of
+    classes: Vec<ClassCompiler>,
+    /// Parse assert statements without emitting any code
+    pub strip_asserts: bool,
 }
 
 impl Parser {
@@ -26,8 +37,10 @@ impl Parser {
             current: None,
             previous: None,
             enclosing: Vec::new(),
+            classes: Vec::new(),
             source,
             compiler,
+            strip_asserts: false,
         }
     }
 
@@ -46,6 +59,10 @@ impl Parser {
             self.advance()?;
             return self.var_declaration();
         }
+        if self.match_token_type(TokenType::Const) {
+            self.advance()?;
+            return self.const_declaration();
+        }
         self.statement()
     }
 
@@ -58,6 +75,9 @@ impl Parser {
     /// var a;
     /// ```
     fn var_declaration(&mut self) -> Result<(), InterpretError> {
+        if self.match_token_type(TokenType::LeftParen) {
+            return self.destructuring_declaration();
+        }
         let global = self.parse_variable("Expect variable name.")?;
 
         if self.match_token_type(TokenType::Equal) {
@@ -76,20 +96,68 @@ impl Parser {
         Ok(())
     }
 
+    /// parse declaration like
+    /// ```
+    /// var (quotient, remainder) = divmod(7, 2);
+    /// ```
+    /// The initializer must be a tuple with as many items as the variables.
+    fn destructuring_declaration(&mut self) -> Result<(), InterpretError> {
+        self.advance()?;
+        // all variables are declared before the initializer so that it can't refer to them
+        let mut globals = vec![self.parse_variable("Expect variable name.")?];
+        while self.match_token_type(TokenType::Comma) {
+            self.advance()?;
+            globals.push(self.parse_variable("Expect variable name.")?);
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after variable names.")?;
+        self.consume(TokenType::Equal, "Expect '=' after variable names.")?;
+        self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after expression.")?;
+
+        let line = self.previous.as_ref().unwrap().line;
+        let count = globals.len();
+        self.compiler.emit_byte(OpCode::OpUnpack { count }, line);
+        self.compiler.define_variables(&globals, line);
+        Ok(())
+    }
+
+    /// parse declaration like
+    /// ```
+    /// const a = 1;
+    /// ```
+    /// Constants must be initialized and can't be assigned afterwards.
+    fn const_declaration(&mut self) -> Result<(), InterpretError> {
+        let global = self.parse_variable("Expect constant name.")?;
+        self.consume(TokenType::Equal, "Expect '=' after constant name.")?;
+        self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after expression.")?;
+        let line = self.previous.as_ref().unwrap().line;
+        self.compiler.define_constant(global, line);
+        Ok(())
+    }
+
     fn parse_variable(&mut self, message: &str) -> Result<usize, InterpretError> {
         self.consume(TokenType::Identifier, message)?;
-        self.compiler
-            .declare_variable(self.previous.as_ref().unwrap())?;
+        let name = self.previous.clone().unwrap();
+        self.declare_named_variable(&name)
+    }
+
+    /// Returns the index of the name constant for globals, or 0 for locals
+    fn declare_named_variable(&mut self, name: &Token) -> Result<usize, InterpretError> {
+        self.compiler.declare_variable(name)?;
         if self.compiler.is_local() {
             return Ok(0);
         }
 
-        let global_variable_name = self.previous.as_ref().unwrap().lexeme.clone();
-        Ok(self.compiler.identifier_constant(global_variable_name))
+        Ok(self.compiler.identifier_constant(name.lexeme.clone()))
     }
 
     fn statement(&mut self) -> Result<(), InterpretError> {
         match self.current.as_ref().unwrap().token_type {
+            TokenType::Class => {
+                self.advance()?;
+                self.class_statement()
+            }
             TokenType::Fun => {
                 self.advance()?;
                 self.fun_statement()
@@ -106,6 +174,10 @@ impl Parser {
                 self.advance()?;
                 self.if_statement()
             }
+            TokenType::Match => {
+                self.advance()?;
+                self.match_statement()
+            }
             TokenType::While => {
                 self.advance()?;
                 self.while_statement()
@@ -114,6 +186,30 @@ impl Parser {
                 self.advance()?;
                 self.for_statement()
             }
+            TokenType::Break => {
+                self.advance()?;
+                self.break_statement()
+            }
+            TokenType::Continue => {
+                self.advance()?;
+                self.continue_statement()
+            }
+            TokenType::Throw => {
+                self.advance()?;
+                self.throw_statement()
+            }
+            TokenType::Assert => {
+                self.advance()?;
+                self.assert_statement()
+            }
+            TokenType::Try => {
+                self.advance()?;
+                self.try_statement()
+            }
+            TokenType::Import => {
+                self.advance()?;
+                self.import_statement()
+            }
             TokenType::LeftBrace => {
                 self.advance()?;
                 self.compiler.begin_scope();
@@ -144,16 +240,120 @@ impl Parser {
             );
             return Err(InterpretError::CompileError);
         }
+        let keyword = self.previous.clone().unwrap();
         if self.match_token_type(TokenType::Semicolon) {
-            let line = self.previous.as_ref().unwrap().line;
-            self.compiler.emit_byte(OpCode::OpNil, line);
-            self.compiler.emit_byte(OpCode::OpReturn, line);
+            self.advance()?;
+            self.compiler.emit_implicit_return_value(keyword.line);
+            self.compiler.emit_exit(Completion::Return, keyword.line);
             return Ok(());
         }
+        if self.compiler.function_type == FunctionType::Initializer {
+            error_report::report_error(
+                self.current.as_ref().unwrap(),
+                "Can't return a value from an initializer.",
+            );
+            return Err(InterpretError::CompileError);
+        }
         self.expression()?;
+        // multiple values like `return a, b;` are returned as a tuple
+        let mut item_count = 1;
+        while self.match_token_type(TokenType::Comma) {
+            self.advance()?;
+            self.expression()?;
+            item_count += 1;
+        }
+        if item_count > 1 {
+            let line = self.previous.as_ref().unwrap().line;
+            self.compiler
+                .emit_byte(OpCode::OpBuildTuple { item_count }, line);
+        }
         self.consume(TokenType::Semicolon, "Expect ';' after return value.")?;
+        self.compiler.emit_exit(Completion::Return, keyword.line);
+        Ok(())
+    }
+
+    /// parse class declaration like
+    /// ```
+    /// class Foo < Base {
+    ///   init(bar) {
+    ///     this.bar = bar;
+    ///   }
+    /// }
+    /// ```
+    fn class_statement(&mut self) -> Result<(), InterpretError> {
+        self.consume(TokenType::Identifier, "Expect class name.")?;
+        let class_name = self.previous.clone().unwrap();
+        let name_constant = self.compiler.identifier_constant(class_name.lexeme.clone());
+        self.compiler.declare_variable(&class_name)?;
+
+        self.compiler.emit_byte(
+            OpCode::OpClass {
+                index: name_constant,
+            },
+            class_name.line,
+        );
+        self.compiler
+            .define_variable(name_constant, class_name.line);
+
+        self.classes.push(ClassCompiler::new());
+        if self.match_token_type(TokenType::Less) {
+            self.superclass(&class_name)?;
+        }
+
+        // Load the class onto the stack so that methods can be attached to it
+        self.named_variable(class_name, false)?;
+        self.consume(TokenType::LeftBrace, "Expect '{' before class body.")?;
+        while !self.match_token_type(TokenType::RightBrace)
+            && !self.match_token_type(TokenType::EOF)
+        {
+            self.method()?;
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after class body.")?;
+        let line = self.previous.as_ref().unwrap().line;
+        self.compiler.emit_pop(line);
+        if self.classes.pop().unwrap().has_superclass {
+            self.compiler.end_scope(line);
+        }
+        Ok(())
+    }
+
+    /// Inherit methods from the superclass.
+    /// The superclass is stored in a local variable named `super`
+    /// so that methods can capture it to resolve `super` calls.
+    fn superclass(&mut self, class_name: &Token) -> Result<(), InterpretError> {
+        self.advance()?;
+        self.consume(TokenType::Identifier, "Expect superclass name.")?;
+        let superclass_name = self.previous.clone().unwrap();
+        if superclass_name.lexeme == class_name.lexeme {
+            error_report::report_error(&superclass_name, "A class can't inherit from itself.");
+            return Err(InterpretError::CompileError);
+        }
+        self.named_variable(superclass_name.clone(), false)?;
+
+        self.compiler.begin_scope();
+        let super_token = Token::new(TokenType::Super, "super".to_string(), superclass_name.line);
+        self.compiler.add_local(super_token);
+        self.compiler.define_local_variable();
+
+        self.named_variable(class_name.clone(), false)?;
+        self.compiler
+            .emit_byte(OpCode::OpInherit, superclass_name.line);
+        self.classes.last_mut().unwrap().has_superclass = true;
+        Ok(())
+    }
+
+    fn method(&mut self) -> Result<(), InterpretError> {
+        self.consume(TokenType::Identifier, "Expect method name.")?;
+        let name = self.previous.as_ref().unwrap().lexeme.clone();
+        let index = self.compiler.identifier_constant(name.clone());
+        let function_type = if name == "init" {
+            FunctionType::Initializer
+        } else {
+            FunctionType::Method
+        };
+        self.parse_function(function_type, name)?;
         let line = self.previous.as_ref().unwrap().line;
-        self.compiler.emit_byte(OpCode::OpReturn, line);
+        self.compiler.emit_byte(OpCode::OpMethod { index }, line);
         Ok(())
     }
 
@@ -161,45 +361,160 @@ impl Parser {
         let global = self.parse_variable("Expect function name.")?;
         // mark as initialized to be able to be referenced in function body
         self.compiler.mark_initialized();
-        self.parse_function(FunctionType::Function)?;
+        let name = self.previous.as_ref().unwrap().lexeme.clone();
+        self.parse_function(FunctionType::Function, name)?;
         let line = self.previous.as_ref().unwrap().line;
         self.compiler.define_variable(global, line);
         Ok(())
     }
 
-    fn parse_function(&mut self, function_type: FunctionType) -> Result<(), InterpretError> {
+    fn parse_function(
+        &mut self,
+        function_type: FunctionType,
+        name: String,
+    ) -> Result<(), InterpretError> {
+        self.begin_function(function_type, name);
+        self.consume(TokenType::LeftParen, "Expect '(' after function name.")?;
+        self.parse_parameters()?;
+        self.consume(TokenType::LeftBrace, "Expect '{' before function body.")?;
+        self.block()?;
+        self.end_function();
+        Ok(())
+    }
+
+    /// Switch to a new compiler for the function body
+    fn begin_function(&mut self, function_type: FunctionType, name: String) {
         let previous_compiler = self.compiler.clone();
         self.enclosing.push(previous_compiler);
         self.compiler = Compiler::new(function_type);
-        let function_name = self.previous.as_ref().unwrap().lexeme.clone();
-        self.compiler.function.name = function_name;
-
+        self.compiler.function.name = name;
         self.compiler.begin_scope();
-        self.parse_argument()?;
-        self.block()?;
+    }
 
+    /// Go back to the enclosing compiler and emit the closure of the function
+    fn end_function(&mut self) {
         let line = self.previous.as_ref().unwrap().line;
-        let function = Obj::Function(self.compiler.end_compiler(line));
+        let function = Obj::Function(Rc::new(self.compiler.end_compiler(line)));
         self.compiler = self.enclosing.pop().unwrap();
-        self.compiler.emit_constant(Value::Obj(function), line);
-        Ok(())
+        let index = self
+            .compiler
+            .current_chunk_as_mut()
+            .add_constant(Value::Obj(function));
+        self.compiler.emit_byte(OpCode::OpClosure { index }, line);
     }
 
-    fn parse_argument(&mut self) -> Result<(), InterpretError> {
-        self.consume(TokenType::LeftParen, "Expect '(' after function name.")?;
+    /// Parameters after the opening parenthesis up to the closing one
+    fn parse_parameters(&mut self) -> Result<(), InterpretError> {
         if !self.match_token_type(TokenType::RightParen) {
             loop {
-                self.compiler.function.arity += 1;
+                if self.match_token_type(TokenType::DotDotDot) {
+                    self.advance()?;
+                    self.parse_variable("Expect parameter name.")?;
+                    self.compiler.define_local_variable();
+                    self.compiler.function.variadic = true;
+                    if !self.match_token_type(TokenType::RightParen) {
+                        error_report::report_error(
+                            self.current.as_ref().unwrap(),
+                            "Rest parameter must be the last parameter.",
+                        );
+                        return Err(InterpretError::CompileError);
+                    }
+                    break;
+                }
+                let index = self.compiler.function.max_arity;
+                self.compiler.function.max_arity += 1;
                 self.parse_variable("Expect parameter name.")?;
                 self.compiler.define_local_variable();
+                if self.match_token_type(TokenType::Equal) {
+                    self.advance()?;
+                    self.default_value(index)?;
+                } else if self.compiler.function.min_arity != index {
+                    error_report::report_error(
+                        self.previous.as_ref().unwrap(),
+                        "Parameter without default value can't follow one with a default.",
+                    );
+                    return Err(InterpretError::CompileError);
+                } else {
+                    self.compiler.function.min_arity += 1;
+                }
                 if !self.match_token_type(TokenType::Comma) {
                     break;
                 }
                 self.advance()?;
             }
         }
-        self.consume(TokenType::RightParen, "Expect ')' after parameters.")?;
-        self.consume(TokenType::LeftBrace, "Expect '{' before function body.")
+        self.consume(TokenType::RightParen, "Expect ')' after parameters.")
+    }
+
+    /// The default value is evaluated at the start of every call
+    /// which doesn't pass the argument for the parameter at `index`
+    fn default_value(&mut self, index: usize) -> Result<(), InterpretError> {
+        let line = self.previous.as_ref().unwrap().line;
+        let skip_jump = self
+            .compiler
+            .emit_jump(OpCode::OpJumpIfPassed { index, offset: 0 }, line);
+        self.expression()?;
+        // slot 0 is reserved, so parameters start at slot 1
+        self.compiler
+            .emit_byte(OpCode::OpSetLocal { index: index + 1 }, line);
+        self.compiler.emit_pop(line);
+        self.compiler.patch_jump(skip_jump);
+        Ok(())
+    }
+
+    /// Function expression like `fun (a, b) { return a + b; }`
+    fn lambda(&mut self) -> Result<(), InterpretError> {
+        self.parse_function(FunctionType::Function, ANONYMOUS_FUNCTION_NAME.to_string())
+    }
+
+    /// Arrow function like `(a, b) => a + b` or `(a) => { print a; }`.
+    /// The opening parenthesis is already consumed.
+    fn arrow_function(&mut self) -> Result<(), InterpretError> {
+        self.begin_function(FunctionType::Function, ANONYMOUS_FUNCTION_NAME.to_string());
+        self.parse_parameters()?;
+        self.consume(TokenType::Arrow, "Expect '=>' after parameters.")?;
+        if self.match_token_type(TokenType::LeftBrace) {
+            self.advance()?;
+            self.block()?;
+        } else {
+            self.expression()?;
+            let line = self.previous.as_ref().unwrap().line;
+            self.compiler.emit_byte(OpCode::OpReturn, line);
+        }
+        self.end_function();
+        Ok(())
+    }
+
+    /// Whether the parenthesized tokens after `(` are followed by `=>`.
+    /// Scans ahead so that no token is consumed.
+    fn is_arrow_function(&mut self) -> bool {
+        let mut token_type = self.current.as_ref().unwrap().token_type.clone();
+        self.scan_ahead(|source| {
+            let mut depth = 1;
+            loop {
+                match token_type {
+                    TokenType::LeftParen => depth += 1,
+                    TokenType::RightParen => depth -= 1,
+                    TokenType::Interpolation if skip_interpolation(source) => (),
+                    TokenType::EOF | TokenType::Error | TokenType::Interpolation => return false,
+                    _ => (),
+                }
+                if depth == 0 {
+                    break;
+                }
+                token_type = scan::scan_token(source).token_type;
+            }
+            scan::scan_token(source).token_type == TokenType::Arrow
+        })
+    }
+
+    /// Scan the tokens after the current one with `lookahead`,
+    /// then go back so that they are scanned again by the parser
+    fn scan_ahead<T>(&mut self, lookahead: impl FnOnce(&mut Source) -> T) -> T {
+        let position = self.source.position();
+        let result = lookahead(&mut self.source);
+        self.source.restore(position);
+        result
     }
 
     /// The order of execution in for loop:
@@ -212,12 +527,16 @@ impl Parser {
     fn for_statement(&mut self) -> Result<(), InterpretError> {
         self.compiler.begin_scope();
         self.consume(TokenType::LeftParen, "Expect '(' after if.")?;
+        if let Some(variable) = self.for_in_variable()? {
+            return self.for_in_loop(variable);
+        }
         self.for_loop_init()?;
         // For loop restarts after the initialization
-        let loop_start = self.compiler.current_chunk_as_ref().code.len() - 1;
+        let loop_start = self.compiler.current_code_address();
         let loop_exit_jump = self.for_loop_condition()?;
 
         let jump_after_body = self.for_loop_increment(loop_start)?;
+        self.compiler.begin_loop(jump_after_body);
         self.statement()?;
 
         let line = self.previous.as_ref().unwrap().line;
@@ -227,6 +546,68 @@ impl Parser {
             self.compiler.patch_jump(jump);
             self.compiler.emit_pop(line);
         }
+        self.compiler.end_loop();
+        self.compiler.end_scope(line);
+        Ok(())
+    }
+
+    /// Consume the beginning of the clause of a for-in loop like `x in` or `var x in`
+    /// and return the loop variable. Other clauses are left as they are.
+    fn for_in_variable(&mut self) -> Result<Option<Token>, InterpretError> {
+        let current = self.current.clone().unwrap();
+        let (variable, keyword) = self.scan_ahead(|source| {
+            let variable = if current.token_type == TokenType::Var {
+                scan::scan_token(source)
+            } else {
+                current
+            };
+            (variable, scan::scan_token(source))
+        });
+        if variable.token_type != TokenType::Identifier
+            || keyword.token_type != TokenType::Identifier
+            || keyword.lexeme != "in"
+        {
+            return Ok(None);
+        }
+        if self.match_token_type(TokenType::Var) {
+            self.advance()?;
+        }
+        self.advance()?;
+        self.advance()?;
+        Ok(Some(variable))
+    }
+
+    /// The rest of a loop like `for (x in iterable) body` after `in`.
+    /// The iterator is kept in a hidden local and the loop variable is
+    /// a new local in each iteration so that closures capture each value.
+    fn for_in_loop(&mut self, variable: Token) -> Result<(), InterpretError> {
+        self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
+        let line = self.previous.as_ref().unwrap().line;
+        self.compiler.emit_byte(OpCode::OpGetIterator, line);
+        let iterator = self.compiler.add_hidden_local();
+
+        let loop_start = self.compiler.current_code_address();
+        self.compiler
+            .emit_byte(OpCode::OpIterate { index: iterator }, line);
+        let exit_jump = self.compiler.emit_jump(
+            OpCode::OpJumpIfExhausted {
+                index: iterator,
+                offset: 0,
+            },
+            line,
+        );
+        self.compiler.begin_loop(loop_start);
+        self.compiler.begin_scope();
+        self.compiler.declare_variable(&variable)?;
+        self.compiler.define_local_variable();
+        self.statement()?;
+
+        let line = self.previous.as_ref().unwrap().line;
+        self.compiler.end_scope(line);
+        self.compiler.emit_jump_back(loop_start, line);
+        self.compiler.patch_jump(exit_jump);
+        self.compiler.end_loop();
         self.compiler.end_scope(line);
         Ok(())
     }
@@ -275,7 +656,7 @@ impl Parser {
 
         let line = self.previous.as_ref().unwrap().line;
         let body_jump = self.compiler.emit_jump(OpCode::OpJump { offset: 0 }, line);
-        let increment_start = self.compiler.current_chunk_as_ref().code.len() - 1;
+        let increment_start = self.compiler.current_code_address();
         self.expression()?;
         let line = self.previous.as_ref().unwrap().line;
         self.compiler.emit_pop(line);
@@ -292,99 +673,545 @@ impl Parser {
     }
 
     fn while_statement(&mut self) -> Result<(), InterpretError> {
-        let code_size = self.compiler.current_chunk_as_ref().code.len();
-        let loop_start = code_size - 1;
+        let loop_start = self.compiler.current_code_address();
         self.condition()?;
         let line = self.previous.as_ref().unwrap().line;
         let exit_jump = self
             .compiler
             .emit_jump(OpCode::OpJumpIfFalse { offset: 0 }, line);
         self.compiler.emit_pop(line);
+        self.compiler.begin_loop(loop_start);
         self.statement()?;
 
         let line = self.previous.as_ref().unwrap().line;
         self.compiler.emit_jump_back(loop_start, line);
         self.compiler.patch_jump(exit_jump);
         self.compiler.emit_pop(line);
+        self.compiler.end_loop();
         Ok(())
     }
 
-    fn condition(&mut self) -> Result<(), InterpretError> {
-        self.consume(TokenType::LeftParen, "Expect '(' before condition.")?;
-        self.expression()?;
-        self.consume(TokenType::RightParen, "Expect ')' after condition.")
+    /// Jump to the end of the innermost loop
+    fn break_statement(&mut self) -> Result<(), InterpretError> {
+        let keyword = self.previous.clone().unwrap();
+        if self.compiler.loops.is_empty() {
+            error_report::report_error(&keyword, "Can't use 'break' outside of a loop.");
+            return Err(InterpretError::CompileError);
+        }
+        self.consume(TokenType::Semicolon, "Expect ';' after 'break'.")?;
+        self.compiler.emit_exit(Completion::Break, keyword.line);
+        Ok(())
     }
 
-    fn if_statement(&mut self) -> Result<(), InterpretError> {
-        self.condition()?;
-        let line = self.previous.as_ref().unwrap().line;
-        let then_jump = self
-            .compiler
-            .emit_jump(OpCode::OpJumpIfFalse { offset: 0 }, line);
-        self.compiler.emit_pop(line);
-        self.statement()?;
-
-        let line = self.previous.as_ref().unwrap().line;
-        let else_jump = self.compiler.emit_jump(OpCode::OpJump { offset: 0 }, line);
-        self.compiler.patch_jump(then_jump);
-        self.compiler.emit_pop(line);
-        if self.match_token_type(TokenType::Else) {
-            self.advance()?;
-            self.statement()?;
+    /// Jump to the condition of the innermost loop,
+    /// or to the increment clause in a for loop
+    fn continue_statement(&mut self) -> Result<(), InterpretError> {
+        let keyword = self.previous.clone().unwrap();
+        if self.compiler.loops.is_empty() {
+            error_report::report_error(&keyword, "Can't use 'continue' outside of a loop.");
+            return Err(InterpretError::CompileError);
         }
-        self.compiler.patch_jump(else_jump);
+        self.consume(TokenType::Semicolon, "Expect ';' after 'continue'.")?;
+        self.compiler.emit_exit(Completion::Continue, keyword.line);
         Ok(())
     }
 
-    fn expression_statement(&mut self) -> Result<(), InterpretError> {
+    fn throw_statement(&mut self) -> Result<(), InterpretError> {
         self.expression()?;
-        self.consume(TokenType::Semicolon, "Expect ';' after expression.")?;
+        self.consume(TokenType::Semicolon, "Expect ';' after thrown value.")?;
         let line = self.previous.as_ref().unwrap().line;
-        self.compiler.emit_pop(line);
+        self.compiler.emit_byte(OpCode::OpThrow, line);
         Ok(())
     }
 
-    fn print_statement(&mut self) -> Result<(), InterpretError> {
+    /// parse assert statement like
+    /// ```
+    /// assert index < len(list), "index is out of bounds";
+    /// ```
+    /// The message is optional and only evaluated when the assertion fails.
+    /// The error reports the source text of the condition, which is captured from the source.
+    fn assert_statement(&mut self) -> Result<(), InterpretError> {
+        let keyword_line = self.previous.as_ref().unwrap().line;
+        let assert_start = self.compiler.current_code_address();
+        let constant_count = self.compiler.current_constant_count();
+        // a yield in a stripped assertion doesn't make the function a generator
+        let generator = self.compiler.function.generator;
+        // the source is scanned up to the current token, which is the first token of the condition
+        let condition_start = self.source.start;
         self.expression()?;
-        self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
+        let condition = self.source.text[condition_start..self.source.start].trim_end();
+        let message = format!("Assertion failed at line {keyword_line}: {condition}");
         let line = self.previous.as_ref().unwrap().line;
-        self.compiler.emit_byte(OpCode::OpPrint, line);
+        let fail_jump = self
+            .compiler
+            .emit_jump(OpCode::OpJumpIfFalse { offset: 0 }, line);
+        self.compiler.emit_pop(line);
+        let end_jump = self.compiler.emit_jump(OpCode::OpJump { offset: 0 }, line);
+        self.compiler.patch_jump(fail_jump);
+        self.compiler.emit_pop(line);
+
+        if self.match_token_type(TokenType::Comma) {
+            self.advance()?;
+            self.expression()?;
+        } else {
+            self.compiler.emit_byte(OpCode::OpNil, line);
+        }
+        self.consume(TokenType::Semicolon, "Expect ';' after assertion.")?;
+        if self.strip_asserts {
+            self.compiler
+                .remove_code_since(assert_start, constant_count);
+            self.compiler.function.generator = generator;
+            return Ok(());
+        }
+        let index = self
+            .compiler
+            .current_chunk_as_mut()
+            .add_constant(Value::LString(message));
+        self.compiler
+            .emit_byte(OpCode::OpAssert { index }, keyword_line);
+        self.compiler.patch_jump(end_jump);
         Ok(())
     }
 
-    /// Consume the next token from self.source.
-    /// self.previous will be the current token and self.current will be the next token.
-    fn advance(&mut self) -> Result<(), InterpretError> {
-        self.previous = self.current.clone();
-        let token = scan::scan_token(&mut self.source);
-        if let TokenType::Error = token.token_type {
-            error_report::report_error(&token, &token.lexeme);
-            return Err(InterpretError::CompileError);
+    /// parse import statement like
+    /// ```
+    /// import "path/to/module.lox" as module;
+    /// ```
+    /// which binds the module object, or
+    /// ```
+    /// import { a, b } from "path/to/module.lox";
+    /// ```
+    /// which binds the exports of the module.
+    fn import_statement(&mut self) -> Result<(), InterpretError> {
+        if !self.match_token_type(TokenType::LeftBrace) {
+            let path = self.module_path()?;
+            self.consume_contextual_keyword("as", "Expect 'as' after module path.")?;
+            let global = self.parse_variable("Expect module name.")?;
+            let line = self.previous.as_ref().unwrap().line;
+            self.compiler
+                .emit_byte(OpCode::OpImport { index: path }, line);
+            self.consume(TokenType::Semicolon, "Expect ';' after import.")?;
+            self.compiler.define_variable(global, line);
+            return Ok(());
+        }
+
+        self.advance()?;
+        let mut names = Vec::new();
+        loop {
+            self.consume(TokenType::Identifier, "Expect name to import.")?;
+            names.push(self.previous.clone().unwrap());
+            if !self.match_token_type(TokenType::Comma) {
+                break;
+            }
+            self.advance()?;
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after imported names.")?;
+        self.consume_contextual_keyword("from", "Expect 'from' after imported names.")?;
+        let path = self.module_path()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after import.")?;
+        for name in names {
+            let global = self.declare_named_variable(&name)?;
+            // modules are cached, so only the first import runs the module
+            self.compiler
+                .emit_byte(OpCode::OpImport { index: path }, name.line);
+            let index = self.compiler.identifier_constant(name.lexeme.clone());
+            self.compiler
+                .emit_byte(OpCode::OpGetProperty { index }, name.line);
+            self.compiler.define_variable(global, name.line);
         }
-        self.current = Some(token);
         Ok(())
     }
 
-    fn consume(&mut self, token_type: TokenType, message: &str) -> Result<(), InterpretError> {
-        if !self.match_token_type(token_type) {
-            error_report::report_error(self.current.as_ref().unwrap(), message);
+    /// Returns the index of the path constant
+    fn module_path(&mut self) -> Result<usize, InterpretError> {
+        self.consume(TokenType::LString, "Expect module path string.")?;
+        let path = scan::string_content(self.previous.as_ref().unwrap());
+        Ok(self
+            .compiler
+            .current_chunk_as_mut()
+            .add_constant(Value::LString(path)))
+    }
+
+    /// `as` and `from` are only keywords in import statements,
+    /// so they can still be used as names elsewhere.
+    fn consume_contextual_keyword(
+        &mut self,
+        keyword: &str,
+        message: &str,
+    ) -> Result<(), InterpretError> {
+        let current = self.current.as_ref().unwrap();
+        if current.token_type != TokenType::Identifier || current.lexeme != keyword {
+            error_report::report_error(current, message);
             return Err(InterpretError::CompileError);
         }
         self.advance()
     }
 
-    fn expression(&mut self) -> Result<(), InterpretError> {
-        self.parse_precedence(Precedence::Assignment)
-    }
+    /// parse try statement like
+    /// ```
+    /// try {
+    ///   risky();
+    /// } catch (error) {
+    ///   print error;
+    /// } finally {
+    ///   cleanup();
+    /// }
+    /// ```
+    /// The finally block is compiled once. Every way out of the try and catch blocks,
+    /// including exceptions, `return`, `break` and `continue`, jumps to it with a completion,
+    /// which a jump table after the finally block carries out.
+    fn try_statement(&mut self) -> Result<(), InterpretError> {
+        let line = self.previous.as_ref().unwrap().line;
+        self.compiler.begin_scope();
+        self.compiler.emit_byte(OpCode::OpNil, line);
+        let value_slot = self.compiler.add_hidden_local();
+        self.compiler.emit_byte(OpCode::OpNil, line);
+        let completion_slot = self.compiler.add_hidden_local();
+        let try_block = TryBlock::new(self.compiler.env.scope_depth, value_slot, completion_slot);
+        self.compiler.try_blocks.push(try_block);
+
+        let catch_jump = self.compiler.emit_jump(OpCode::OpTry { offset: 0 }, line);
+        self.braced_block("Expect '{' before block.")?;
+        let line = self.previous.as_ref().unwrap().line;
+        self.compiler.emit_byte(OpCode::OpEndTry, line);
+        self.compiler.jump_to_finally(Completion::Normal, line);
 
-    fn parse_precedence(&mut self, precedence: Precedence) -> Result<(), InterpretError> {
-        self.advance()?;
-        let previous_token = self.previous.as_ref().unwrap();
-        let can_assign = precedence.clone() as u32 <= Precedence::Assignment as u32;
-        match precedence::get_rule(&previous_token.token_type).prefix {
-            None => {
-                error_report::report_error(previous_token, "Expect expression");
-                return Err(InterpretError::CompileError);
+        // The VM jumps here with the exception on top of the stack
+        self.compiler.patch_jump(catch_jump);
+        let has_catch = self.match_token_type(TokenType::Catch);
+        if has_catch {
+            self.advance()?;
+            self.compiler.begin_scope();
+            self.consume(TokenType::LeftParen, "Expect '(' after 'catch'.")?;
+            self.parse_variable("Expect exception variable name.")?;
+            self.compiler.define_local_variable();
+            self.consume(
+                TokenType::RightParen,
+                "Expect ')' after exception variable.",
+            )?;
+            // exceptions thrown in the catch block still run the finally block
+            let rethrow_jump = self.compiler.emit_jump(OpCode::OpTry { offset: 0 }, line);
+            self.braced_block("Expect '{' before block.")?;
+            let line = self.previous.as_ref().unwrap().line;
+            self.compiler.emit_byte(OpCode::OpEndTry, line);
+            // discard the exception variable
+            self.compiler.end_scope(line);
+            self.compiler.jump_to_finally(Completion::Normal, line);
+
+            // The new exception is on top of the exception variable
+            self.compiler.patch_jump(rethrow_jump);
+            self.compiler
+                .emit_byte(OpCode::OpSetLocal { index: value_slot }, line);
+            self.compiler.emit_pop(line);
+            self.compiler.emit_pop(line);
+        } else {
+            self.compiler
+                .emit_byte(OpCode::OpSetLocal { index: value_slot }, line);
+            self.compiler.emit_pop(line);
+        }
+        self.compiler.set_completion(Completion::Throw, line);
+
+        let try_block = self.compiler.try_blocks.pop().unwrap();
+        for jump in try_block.finally_jumps {
+            self.compiler.patch_jump(jump);
+        }
+        if self.match_token_type(TokenType::Finally) {
+            self.advance()?;
+            self.braced_block("Expect '{' before finally block.")?;
+        } else if !has_catch {
+            error_report::report_error(
+                self.current.as_ref().unwrap(),
+                "Expect 'catch' or 'finally' after try block.",
+            );
+            return Err(InterpretError::CompileError);
+        }
+
+        // Carry out the completion, where the normal one continues after the statement
+        let line = self.previous.as_ref().unwrap().line;
+        self.compiler.emit_byte(
+            OpCode::OpGetLocal {
+                index: completion_slot,
+            },
+            line,
+        );
+        let jump_table = self.compiler.emit_jump_table(line);
+        let mut targets = vec![None; COMPLETION_COUNT];
+        targets[Completion::Throw as usize] = Some(self.compiler.current_code_address());
+        self.compiler
+            .emit_byte(OpCode::OpGetLocal { index: value_slot }, line);
+        self.compiler.emit_byte(OpCode::OpThrow, line);
+        for completion in try_block.early_exits {
+            targets[completion as usize] = Some(self.compiler.current_code_address());
+            if completion == Completion::Return {
+                self.compiler
+                    .emit_byte(OpCode::OpGetLocal { index: value_slot }, line);
+            }
+            self.compiler.emit_exit(completion, line);
+        }
+        let end = self.compiler.current_code_address();
+        self.compiler.patch_jump_table(jump_table, 0, &targets, end);
+        self.compiler.end_scope(line);
+        Ok(())
+    }
+
+    fn braced_block(&mut self, message: &str) -> Result<(), InterpretError> {
+        self.consume(TokenType::LeftBrace, message)?;
+        self.compiler.begin_scope();
+        self.block()?;
+        let line = self.previous.as_ref().unwrap().line;
+        self.compiler.end_scope(line);
+        Ok(())
+    }
+
+    fn condition(&mut self) -> Result<(), InterpretError> {
+        self.consume(TokenType::LeftParen, "Expect '(' before condition.")?;
+        self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after condition.")
+    }
+
+    fn if_statement(&mut self) -> Result<(), InterpretError> {
+        self.condition()?;
+        let line = self.previous.as_ref().unwrap().line;
+        let then_jump = self
+            .compiler
+            .emit_jump(OpCode::OpJumpIfFalse { offset: 0 }, line);
+        self.compiler.emit_pop(line);
+        self.statement()?;
+
+        let line = self.previous.as_ref().unwrap().line;
+        let else_jump = self.compiler.emit_jump(OpCode::OpJump { offset: 0 }, line);
+        self.compiler.patch_jump(then_jump);
+        self.compiler.emit_pop(line);
+        if self.match_token_type(TokenType::Else) {
+            self.advance()?;
+            self.statement()?;
+        }
+        self.compiler.patch_jump(else_jump);
+        Ok(())
+    }
+
+    /// Parse a statement like
+    /// ```
+    /// match (value) {
+    ///     case 1, 2 => print "small";
+    ///     case 3 if verbose => print "three";
+    ///     default => print "other";
+    /// }
+    /// ```
+    /// The value is evaluated once and kept in a hidden local.
+    fn match_statement(&mut self) -> Result<(), InterpretError> {
+        self.compiler.begin_scope();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'match'.")?;
+        self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after value.")?;
+        let value = self.compiler.add_hidden_local();
+        self.consume(TokenType::LeftBrace, "Expect '{' before match cases.")?;
+
+        if let Some(cases) = self.jump_table_cases() {
+            self.match_with_jump_table(value, &cases)?;
+        } else {
+            self.match_with_comparisons(value)?;
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after match cases.")?;
+        let line = self.previous.as_ref().unwrap().line;
+        self.compiler.end_scope(line);
+        Ok(())
+    }
+
+    /// Compare the value with each pattern in order until one of them is equal
+    /// and its guard, if any, is truthy.
+    fn match_with_comparisons(&mut self, value: usize) -> Result<(), InterpretError> {
+        let mut end_jumps = Vec::new();
+        while self.match_token_type(TokenType::Case) {
+            self.advance()?;
+            let mut body_jumps = Vec::new();
+            loop {
+                let line = self.current.as_ref().unwrap().line;
+                self.compiler
+                    .emit_byte(OpCode::OpGetLocal { index: value }, line);
+                self.expression()?;
+                self.compiler.emit_byte(OpCode::OpEqual, line);
+                let next_pattern = self
+                    .compiler
+                    .emit_jump(OpCode::OpJumpIfFalse { offset: 0 }, line);
+                self.compiler.emit_pop(line);
+                body_jumps.push(self.compiler.emit_jump(OpCode::OpJump { offset: 0 }, line));
+                self.compiler.patch_jump(next_pattern);
+                self.compiler.emit_pop(line);
+                if !self.match_token_type(TokenType::Comma) {
+                    break;
+                }
+                self.advance()?;
+            }
+            let line = self.previous.as_ref().unwrap().line;
+            let next_case = self.compiler.emit_jump(OpCode::OpJump { offset: 0 }, line);
+            for body_jump in body_jumps {
+                self.compiler.patch_jump(body_jump);
+            }
+
+            let guard_jump = if self.match_token_type(TokenType::If) {
+                self.advance()?;
+                self.expression()?;
+                let line = self.previous.as_ref().unwrap().line;
+                let guard_jump = self
+                    .compiler
+                    .emit_jump(OpCode::OpJumpIfFalse { offset: 0 }, line);
+                self.compiler.emit_pop(line);
+                Some(guard_jump)
+            } else {
+                None
+            };
+            self.consume(TokenType::Arrow, "Expect '=>' after case patterns.")?;
+            self.statement()?;
+
+            let line = self.previous.as_ref().unwrap().line;
+            end_jumps.push(self.compiler.emit_jump(OpCode::OpJump { offset: 0 }, line));
+            if let Some(guard_jump) = guard_jump {
+                // try the next case
+                self.compiler.patch_jump(guard_jump);
+                self.compiler.emit_pop(line);
+            }
+            self.compiler.patch_jump(next_case);
+        }
+
+        self.default_case()?;
+        for end_jump in end_jumps {
+            self.compiler.patch_jump(end_jump);
+        }
+        Ok(())
+    }
+
+    /// Jump to the case body through a table indexed by the value.
+    /// `cases` holds the integer patterns of each case, which are already checked by the pre-scan.
+    fn match_with_jump_table(
+        &mut self,
+        value: usize,
+        cases: &[Vec<i64>],
+    ) -> Result<(), InterpretError> {
+        let line = self.previous.as_ref().unwrap().line;
+        self.compiler
+            .emit_byte(OpCode::OpGetLocal { index: value }, line);
+        let jump_table = self.compiler.emit_jump_table(line);
+
+        let mut body_addresses = Vec::new();
+        let mut end_jumps = Vec::new();
+        for _ in cases {
+            while !self.match_token_type(TokenType::Arrow) {
+                self.advance()?;
+            }
+            self.advance()?;
+            body_addresses.push(self.compiler.current_code_address());
+            self.statement()?;
+            let line = self.previous.as_ref().unwrap().line;
+            end_jumps.push(self.compiler.emit_jump(OpCode::OpJump { offset: 0 }, line));
+        }
+        let default_address = self.compiler.current_code_address();
+        self.default_case()?;
+        for end_jump in end_jumps {
+            self.compiler.patch_jump(end_jump);
+        }
+
+        let patterns = cases
+            .iter()
+            .zip(body_addresses)
+            .flat_map(|(patterns, address)| {
+                patterns.iter().map(move |pattern| (*pattern, address))
+            });
+        let min = cases.iter().flatten().min().copied().unwrap();
+        let max = cases.iter().flatten().max().copied().unwrap();
+        let mut targets = vec![None; (max - min) as usize + 1];
+        for (pattern, address) in patterns {
+            // the first case with the value wins
+            let target = &mut targets[(pattern - min) as usize];
+            if target.is_none() {
+                *target = Some(address);
+            }
+        }
+        self.compiler
+            .patch_jump_table(jump_table, min, &targets, default_address);
+        Ok(())
+    }
+
+    fn default_case(&mut self) -> Result<(), InterpretError> {
+        if self.match_token_type(TokenType::Default) {
+            self.advance()?;
+            self.consume(TokenType::Arrow, "Expect '=>' after 'default'.")?;
+            self.statement()?;
+        }
+        Ok(())
+    }
+
+    /// Scan the cases of a match statement ahead without consuming them.
+    /// Returns the patterns of each case if all of them are integer literals without guards
+    /// and dense enough to be dispatched with a jump table.
+    fn jump_table_cases(&mut self) -> Option<Vec<Vec<i64>>> {
+        let current = self.current.clone().unwrap();
+        let cases = self.scan_ahead(|source| scan_cases(source, current))?;
+
+        let pattern_count = cases.iter().map(Vec::len).sum::<usize>();
+        let min = cases.iter().flatten().min()?;
+        let max = cases.iter().flatten().max()?;
+        let span = max.checked_sub(*min)?.checked_add(1)?;
+        if pattern_count < JUMP_TABLE_MIN_PATTERNS || span > 2 * pattern_count as i64 {
+            return None;
+        }
+        Some(cases)
+    }
+
+    fn expression_statement(&mut self) -> Result<(), InterpretError> {
+        self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after expression.")?;
+        let line = self.previous.as_ref().unwrap().line;
+        self.compiler.emit_pop(line);
+        Ok(())
+    }
+
+    fn print_statement(&mut self) -> Result<(), InterpretError> {
+        self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
+        let line = self.previous.as_ref().unwrap().line;
+        self.compiler.emit_byte(OpCode::OpPrint, line);
+        Ok(())
+    }
+
+    /// Consume the next token from self.source.
+    /// self.previous will be the current token and self.current will be the next token.
+    fn advance(&mut self) -> Result<(), InterpretError> {
+        self.previous = self.current.clone();
+        let token = scan::scan_token(&mut self.source);
+        self.replace_current(token)
+    }
+
+    fn replace_current(&mut self, token: Token) -> Result<(), InterpretError> {
+        if let TokenType::Error = token.token_type {
+            error_report::report_error(&token, &token.lexeme);
+            return Err(InterpretError::CompileError);
+        }
+        self.current = Some(token);
+        Ok(())
+    }
+
+    fn consume(&mut self, token_type: TokenType, message: &str) -> Result<(), InterpretError> {
+        if !self.match_token_type(token_type) {
+            error_report::report_error(self.current.as_ref().unwrap(), message);
+            return Err(InterpretError::CompileError);
+        }
+        self.advance()
+    }
+
+    fn expression(&mut self) -> Result<(), InterpretError> {
+        self.parse_precedence(Precedence::Assignment)
+    }
+
+    fn parse_precedence(&mut self, precedence: Precedence) -> Result<(), InterpretError> {
+        self.advance()?;
+        let previous_token = self.previous.as_ref().unwrap();
+        let can_assign = precedence.clone() as u32 <= Precedence::Assignment as u32;
+        match precedence::get_rule(&previous_token.token_type).prefix {
+            None => {
+                error_report::report_error(previous_token, "Expect expression");
+                return Err(InterpretError::CompileError);
             }
             Some(prefix_rule) => self.exec_parse_function(prefix_rule, can_assign)?,
         };
@@ -400,7 +1227,7 @@ impl Parser {
             };
         }
 
-        if can_assign && self.match_token_type(TokenType::Equal) {
+        if can_assign && self.match_assignment_operator() {
             self.advance()?;
             let previous_token = self.previous.as_ref().unwrap();
             error_report::report_error(previous_token, "Invalid assignment target.");
@@ -413,8 +1240,12 @@ impl Parser {
         let previous_token = self.previous.as_ref().unwrap();
         let operator_type = previous_token.token_type.clone();
         let rule = precedence::get_rule(&operator_type);
-        let line = previous_token.line.clone();
-        let precedence = rule.precedence.next();
+        let line = previous_token.line;
+        let precedence = match operator_type {
+            // Right-associative, so the right operand can contain the same operator
+            TokenType::StarStar => rule.precedence,
+            _ => rule.precedence.next(),
+        };
         self.parse_precedence(precedence)?;
 
         match operator_type {
@@ -422,6 +1253,13 @@ impl Parser {
             TokenType::Minus => self.compiler.emit_byte(OpCode::OpSubtract, line),
             TokenType::Star => self.compiler.emit_byte(OpCode::OpMultiply, line),
             TokenType::Slash => self.compiler.emit_byte(OpCode::OpDivide, line),
+            TokenType::Percent => self.compiler.emit_byte(OpCode::OpModulo, line),
+            TokenType::StarStar => self.compiler.emit_byte(OpCode::OpPower, line),
+            TokenType::Ampersand => self.compiler.emit_byte(OpCode::OpBitAnd, line),
+            TokenType::Pipe => self.compiler.emit_byte(OpCode::OpBitOr, line),
+            TokenType::Caret => self.compiler.emit_byte(OpCode::OpBitXor, line),
+            TokenType::LessLess => self.compiler.emit_byte(OpCode::OpShiftLeft, line),
+            TokenType::GreaterGreater => self.compiler.emit_byte(OpCode::OpShiftRight, line),
             TokenType::BangEqual => {
                 self.compiler.emit_byte(OpCode::OpEqual, line);
                 self.compiler.emit_byte(OpCode::OpNot, line);
@@ -449,6 +1287,9 @@ impl Parser {
     }
 
     fn grouping(&mut self) -> Result<(), InterpretError> {
+        if self.is_arrow_function() {
+            return self.arrow_function();
+        }
         self.expression()?;
         self.consume(TokenType::RightParen, "Expect ')' after expression.")
     }
@@ -465,6 +1306,7 @@ impl Parser {
         match operator_type {
             TokenType::Minus => self.compiler.emit_byte(OpCode::OpNegate, line),
             TokenType::Bang => self.compiler.emit_byte(OpCode::OpNot, line),
+            TokenType::Tilde => self.compiler.emit_byte(OpCode::OpBitNot, line),
             _ => (),
         }
         Ok(())
@@ -486,7 +1328,190 @@ impl Parser {
             ParseFn::And => self.and(),
             ParseFn::Or => self.or(),
             ParseFn::Call => self.call(),
+            ParseFn::OptionalCall => self.optional_call(),
+            ParseFn::Coalesce => self.coalesce(),
+            ParseFn::Dot => self.dot(can_assign),
+            ParseFn::This => self.this(),
+            ParseFn::Super => self.super_(),
+            ParseFn::List => self.list(),
+            ParseFn::Subscript => self.subscript(can_assign),
+            ParseFn::Map => self.map(),
+            ParseFn::Interpolation => self.interpolation(),
+            ParseFn::Increment => self.prefix_increment(),
+            ParseFn::Conditional => self.conditional(),
+            ParseFn::Lambda => self.lambda(),
+            ParseFn::Yield => self.yield_(),
+        }
+    }
+
+    /// Map literal like `{"a": 1, "b": 2}`
+    fn map(&mut self) -> Result<(), InterpretError> {
+        let mut entry_count = 0;
+        if !self.match_token_type(TokenType::RightBrace) {
+            loop {
+                self.expression()?;
+                self.consume(TokenType::Colon, "Expect ':' after map key.")?;
+                self.expression()?;
+                entry_count += 1;
+                if !self.match_token_type(TokenType::Comma) {
+                    break;
+                }
+                self.advance()?;
+            }
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after map entries.")?;
+        let line = self.previous.as_ref().unwrap().line;
+        self.compiler
+            .emit_byte(OpCode::OpBuildMap { entry_count }, line);
+        Ok(())
+    }
+
+    /// List literal like `[1, 2, 3]`
+    fn list(&mut self) -> Result<(), InterpretError> {
+        let mut item_count = 0;
+        if !self.match_token_type(TokenType::RightBracket) {
+            loop {
+                self.expression()?;
+                item_count += 1;
+                if !self.match_token_type(TokenType::Comma) {
+                    break;
+                }
+                self.advance()?;
+            }
+        }
+        self.consume(TokenType::RightBracket, "Expect ']' after list items.")?;
+        let line = self.previous.as_ref().unwrap().line;
+        self.compiler
+            .emit_byte(OpCode::OpBuildList { item_count }, line);
+        Ok(())
+    }
+
+    /// Index access like `list[index]` or `map[key] = value`,
+    /// or slicing like `string[start:end]` where either bound may be omitted
+    fn subscript(&mut self, can_assign: bool) -> Result<(), InterpretError> {
+        if self.match_token_type(TokenType::Colon) {
+            let line = self.current.as_ref().unwrap().line;
+            self.compiler.emit_byte(OpCode::OpNil, line);
+            return self.slice();
+        }
+        self.expression()?;
+        if self.match_token_type(TokenType::Colon) {
+            return self.slice();
+        }
+        self.consume(TokenType::RightBracket, "Expect ']' after index.")?;
+        let line = self.previous.as_ref().unwrap().line;
+
+        if can_assign && self.match_token_type(TokenType::Equal) {
+            self.advance()?;
+            self.expression()?;
+            self.compiler.emit_byte(OpCode::OpSetIndex, line);
+        } else {
+            self.compiler.emit_byte(OpCode::OpGetIndex, line);
+        }
+        Ok(())
+    }
+
+    /// The rest of a slice after the start bound, beginning at the `:`
+    fn slice(&mut self) -> Result<(), InterpretError> {
+        self.advance()?;
+        if self.match_token_type(TokenType::RightBracket) {
+            let line = self.current.as_ref().unwrap().line;
+            self.compiler.emit_byte(OpCode::OpNil, line);
+        } else {
+            self.expression()?;
+        }
+        self.consume(TokenType::RightBracket, "Expect ']' after slice.")?;
+        let line = self.previous.as_ref().unwrap().line;
+        self.compiler.emit_byte(OpCode::OpGetSlice, line);
+        Ok(())
+    }
+
+    /// Superclass method access like `super.method`
+    fn super_(&mut self) -> Result<(), InterpretError> {
+        let super_token = self.previous.clone().unwrap();
+        match self.classes.last() {
+            None => {
+                error_report::report_error(&super_token, "Can't use 'super' outside of a class.");
+                return Err(InterpretError::CompileError);
+            }
+            Some(class) if !class.has_superclass => {
+                error_report::report_error(
+                    &super_token,
+                    "Can't use 'super' in a class with no superclass.",
+                );
+                return Err(InterpretError::CompileError);
+            }
+            Some(_) => (),
+        }
+
+        self.consume(TokenType::Dot, "Expect '.' after 'super'.")?;
+        self.consume(TokenType::Identifier, "Expect superclass method name.")?;
+        let name = self.previous.clone().unwrap();
+        let index = self.compiler.identifier_constant(name.lexeme);
+
+        let this_token = Token::new(TokenType::This, "this".to_string(), name.line);
+        self.named_variable(this_token, false)?;
+        self.named_variable(super_token, false)?;
+        self.compiler
+            .emit_byte(OpCode::OpGetSuper { index }, name.line);
+        Ok(())
+    }
+
+    fn this(&mut self) -> Result<(), InterpretError> {
+        if self.classes.is_empty() {
+            error_report::report_error(
+                self.previous.as_ref().unwrap(),
+                "Can't use 'this' outside of a class.",
+            );
+            return Err(InterpretError::CompileError);
         }
+        // `this` can't be assigned to
+        self.variable(false)
+    }
+
+    /// `yield value`, which suspends the coroutine running the function.
+    /// It evaluates to the value passed to the `resume` continuing the coroutine.
+    fn yield_(&mut self) -> Result<(), InterpretError> {
+        let keyword = self.previous.clone().unwrap();
+        match self.compiler.function_type {
+            FunctionType::Script => {
+                error_report::report_error(&keyword, "Can't yield from top-level code.");
+                return Err(InterpretError::CompileError);
+            }
+            FunctionType::Initializer => {
+                error_report::report_error(&keyword, "Can't yield from an initializer.");
+                return Err(InterpretError::CompileError);
+            }
+            FunctionType::Function | FunctionType::Method => (),
+        }
+        self.compiler.function.generator = true;
+        // the value is optional like in `var received = yield;`
+        let current_type = &self.current.as_ref().unwrap().token_type;
+        if precedence::get_rule(current_type).prefix.is_some() {
+            self.expression()?;
+        } else {
+            self.compiler.emit_byte(OpCode::OpNil, keyword.line);
+        }
+        self.compiler.emit_byte(OpCode::OpYield, keyword.line);
+        Ok(())
+    }
+
+    /// Property access like `instance.field` or `instance.field = value`
+    fn dot(&mut self, can_assign: bool) -> Result<(), InterpretError> {
+        self.consume(TokenType::Identifier, "Expect property name after '.'.")?;
+        let name = self.previous.clone().unwrap();
+        let index = self.compiler.identifier_constant(name.lexeme);
+
+        if can_assign && self.match_token_type(TokenType::Equal) {
+            self.advance()?;
+            self.expression()?;
+            self.compiler
+                .emit_byte(OpCode::OpSetProperty { index }, name.line);
+        } else {
+            self.compiler
+                .emit_byte(OpCode::OpGetProperty { index }, name.line);
+        }
+        Ok(())
     }
 
     fn call(&mut self) -> Result<(), InterpretError> {
@@ -496,6 +1521,18 @@ impl Parser {
         Ok(())
     }
 
+    /// `callee?.(arguments)` evaluates to nil without evaluating the arguments if the callee is nil
+    fn optional_call(&mut self) -> Result<(), InterpretError> {
+        let line = self.previous.as_ref().unwrap().line;
+        self.consume(TokenType::LeftParen, "Expect '(' after '?.'.")?;
+        let nil_jump = self
+            .compiler
+            .emit_jump(OpCode::OpJumpIfNil { offset: 0 }, line);
+        self.call()?;
+        self.compiler.patch_jump(nil_jump);
+        Ok(())
+    }
+
     fn arg_list(&mut self) -> Result<usize, InterpretError> {
         let mut arg_count = 0;
         if !self.match_token_type(TokenType::RightParen) {
@@ -519,29 +1556,166 @@ impl Parser {
     }
 
     fn named_variable(&mut self, name: Token, can_assign: bool) -> Result<(), InterpretError> {
-        let arg = self.compiler.resolve_local(&name)?;
-        let (get_op, set_op) = match arg {
-            None => {
-                let index = self.compiler.identifier_constant(name.lexeme);
-                (OpCode::OpGetGlobal { index }, OpCode::OpSetGlobal { index })
-            }
-            Some(index) => (OpCode::OpGetLocal { index }, OpCode::OpSetLocal { index }),
-        };
+        let (get_op, set_op, is_const) = self.resolve_variable(&name)?;
+        let is_assigned = (can_assign && self.match_assignment_operator())
+            || self.match_token_type(TokenType::PlusPlus)
+            || self.match_token_type(TokenType::MinusMinus);
+        if is_const && is_assigned {
+            return Err(constant_assignment_error(&name));
+        }
         if can_assign && self.match_token_type(TokenType::Equal) {
             self.advance()?;
             self.expression()?;
             self.compiler.emit_byte(set_op, name.line);
+        } else if can_assign && self.match_compound_assignment() {
+            // `a += b` is compiled as `a = a + b`
+            self.advance()?;
+            let operator = match self.previous.as_ref().unwrap().token_type {
+                TokenType::PlusEqual => OpCode::OpAdd,
+                TokenType::MinusEqual => OpCode::OpSubtract,
+                TokenType::StarEqual => OpCode::OpMultiply,
+                _ => OpCode::OpDivide,
+            };
+            self.compiler.emit_byte(get_op, name.line);
+            self.expression()?;
+            self.compiler.emit_byte(operator, name.line);
+            self.compiler.emit_byte(set_op, name.line);
+        } else if self.match_token_type(TokenType::PlusPlus)
+            || self.match_token_type(TokenType::MinusMinus)
+        {
+            // Postfix keeps the old value on the stack below the updated one
+            self.advance()?;
+            let operator = self.increment_operator();
+            self.compiler.emit_byte(get_op.clone(), name.line);
+            self.compiler.emit_byte(get_op, name.line);
+            self.compiler.emit_constant(Value::Integer(1), name.line);
+            self.compiler.emit_byte(operator, name.line);
+            self.compiler.emit_byte(set_op, name.line);
+            self.compiler.emit_byte(OpCode::OpPop, name.line);
         } else {
             self.compiler.emit_byte(get_op, name.line);
         }
         Ok(())
     }
 
+    /// `++a` and `--a` evaluate to the updated value.
+    /// Only variables can be incremented, not `++list[0]` or `++instance.field`.
+    fn prefix_increment(&mut self) -> Result<(), InterpretError> {
+        let operator = self.increment_operator();
+        if !self.match_token_type(TokenType::Identifier) {
+            error_report::report_error(
+                self.current.as_ref().unwrap(),
+                "Invalid assignment target.",
+            );
+            return Err(InterpretError::CompileError);
+        }
+        self.advance()?;
+        let name = self.previous.clone().unwrap();
+        if matches!(
+            self.current.as_ref().unwrap().token_type,
+            TokenType::Dot | TokenType::LeftBracket | TokenType::LeftParen
+        ) {
+            error_report::report_error(&name, "Invalid assignment target.");
+            return Err(InterpretError::CompileError);
+        }
+        let (get_op, set_op, is_const) = self.resolve_variable(&name)?;
+        if is_const {
+            return Err(constant_assignment_error(&name));
+        }
+        self.compiler.emit_byte(get_op, name.line);
+        self.compiler.emit_constant(Value::Integer(1), name.line);
+        self.compiler.emit_byte(operator, name.line);
+        self.compiler.emit_byte(set_op, name.line);
+        Ok(())
+    }
+
+    /// Operator of the `++` or `--` token which was just consumed
+    fn increment_operator(&self) -> OpCode {
+        if self.previous.as_ref().unwrap().token_type == TokenType::PlusPlus {
+            OpCode::OpAdd
+        } else {
+            OpCode::OpSubtract
+        }
+    }
+
+    /// Returns the instructions to get and set the variable,
+    /// and whether it's a local constant.
+    /// Constant globals are only known at runtime.
+    fn resolve_variable(&mut self, name: &Token) -> Result<(OpCode, OpCode, bool), InterpretError> {
+        let resolved = if let Some(index) = self.compiler.resolve_local(name)? {
+            (
+                OpCode::OpGetLocal { index },
+                OpCode::OpSetLocal { index },
+                self.compiler.is_const_local(index),
+            )
+        } else if let Some((index, is_const)) = self.resolve_upvalue(self.enclosing.len(), name)? {
+            (
+                OpCode::OpGetUpvalue { index },
+                OpCode::OpSetUpvalue { index },
+                is_const,
+            )
+        } else {
+            let index = self.compiler.identifier_constant(name.lexeme.clone());
+            (
+                OpCode::OpGetGlobal { index },
+                OpCode::OpSetGlobal { index },
+                false,
+            )
+        };
+        Ok(resolved)
+    }
+
+    /// Look up a variable declared in the enclosing functions.
+    /// `depth` is the position of the compiler in the compiler stack,
+    /// where `self.enclosing.len()` refers to the current compiler.
+    /// Returns the index of the upvalue in the compiler at `depth`
+    /// and whether the captured variable is a constant.
+    fn resolve_upvalue(
+        &mut self,
+        depth: usize,
+        name: &Token,
+    ) -> Result<Option<(usize, bool)>, InterpretError> {
+        if depth == 0 {
+            return Ok(None);
+        }
+
+        let enclosing = &mut self.enclosing[depth - 1];
+        if let Some(slot) = enclosing.resolve_local(name)? {
+            enclosing.capture_local(slot);
+            let is_const = enclosing.is_const_local(slot);
+            let index = self.compiler_at(depth).add_upvalue(slot, true);
+            return Ok(Some((index, is_const)));
+        }
+
+        if let Some((index, is_const)) = self.resolve_upvalue(depth - 1, name)? {
+            let index = self.compiler_at(depth).add_upvalue(index, false);
+            return Ok(Some((index, is_const)));
+        }
+
+        Ok(None)
+    }
+
+    fn compiler_at(&mut self, depth: usize) -> &mut Compiler {
+        if depth == self.enclosing.len() {
+            &mut self.compiler
+        } else {
+            &mut self.enclosing[depth]
+        }
+    }
+
+    /// Literals without a fractional part are integers
     fn number(&mut self) -> Result<(), InterpretError> {
         let token = self.previous.as_ref().unwrap();
-        let value = token.lexeme.parse::<f64>().unwrap();
+        let value = if token.lexeme.contains('.') {
+            Value::Number(token.lexeme.parse::<f64>().unwrap())
+        } else if let Ok(integer) = token.lexeme.parse::<i64>() {
+            Value::Integer(integer)
+        } else {
+            error_report::report_error(token, "Integer literal is too large.");
+            return Err(InterpretError::CompileError);
+        };
         let line = token.line;
-        self.compiler.emit_constant(Value::Number(value), line);
+        self.compiler.emit_constant(value, line);
         Ok(())
     }
 
@@ -560,13 +1734,41 @@ impl Parser {
 
     fn string(&mut self) -> Result<(), InterpretError> {
         let token = self.previous.as_ref().unwrap();
-        let value = &token.lexeme;
+        let value = scan::string_content(token);
         let line = token.line;
-        self.compiler
-            .emit_constant(Value::LString(value[1..value.len() - 1].to_string()), line);
+        self.compiler.emit_constant(Value::LString(value), line);
         Ok(())
     }
 
+    /// String interpolation like `"a ${b} c"`, which is compiled into `"a " + b + " c"`
+    /// with OpToString converting the value of each interpolated expression into a string
+    fn interpolation(&mut self) -> Result<(), InterpretError> {
+        self.string()?;
+        loop {
+            self.expression()?;
+            if !self.match_token_type(TokenType::RightBrace) {
+                error_report::report_error(
+                    self.current.as_ref().unwrap(),
+                    "Expect '}' after interpolated expression.",
+                );
+                return Err(InterpretError::CompileError);
+            }
+            let line = self.current.as_ref().unwrap().line;
+            self.compiler.emit_byte(OpCode::OpToString, line);
+            self.compiler.emit_byte(OpCode::OpAdd, line);
+
+            // Scan the rest of the string instead of the token after `}`
+            let rest_of_string = scan::continue_string(&mut self.source);
+            self.replace_current(rest_of_string)?;
+            self.advance()?;
+            self.string()?;
+            self.compiler.emit_byte(OpCode::OpAdd, line);
+            if self.previous.as_ref().unwrap().token_type == TokenType::LString {
+                return Ok(());
+            }
+        }
+    }
+
     fn and(&mut self) -> Result<(), InterpretError> {
         let line = self.previous.as_ref().unwrap().line;
         let end_jump = self
@@ -594,9 +1796,141 @@ impl Parser {
         Ok(())
     }
 
+    /// `value ?? fallback` evaluates to the fallback only if the value is nil,
+    /// unlike `or` which also skips false
+    fn coalesce(&mut self) -> Result<(), InterpretError> {
+        let line = self.previous.as_ref().unwrap().line;
+        let else_jump = self
+            .compiler
+            .emit_jump(OpCode::OpJumpIfNil { offset: 0 }, line);
+        let end_jump = self.compiler.emit_jump(OpCode::OpJump { offset: 0 }, line);
+
+        self.compiler.patch_jump(else_jump);
+        self.compiler.emit_pop(line);
+        self.parse_precedence(Precedence::Coalesce)?;
+        self.compiler.patch_jump(end_jump);
+        Ok(())
+    }
+
+    /// `condition ? then : else`, where `else` may be another conditional
+    fn conditional(&mut self) -> Result<(), InterpretError> {
+        let line = self.previous.as_ref().unwrap().line;
+        let else_jump = self
+            .compiler
+            .emit_jump(OpCode::OpJumpIfFalse { offset: 0 }, line);
+
+        self.compiler.emit_pop(line);
+        self.parse_precedence(Precedence::Conditional)?;
+        self.consume(
+            TokenType::Colon,
+            "Expect ':' after then branch of conditional.",
+        )?;
+        let end_jump = self.compiler.emit_jump(OpCode::OpJump { offset: 0 }, line);
+
+        self.compiler.patch_jump(else_jump);
+        self.compiler.emit_pop(line);
+        self.parse_precedence(Precedence::Conditional)?;
+        self.compiler.patch_jump(end_jump);
+        Ok(())
+    }
+
     fn match_token_type(&self, token_type: TokenType) -> bool {
         self.current.as_ref().unwrap().token_type == token_type
     }
+
+    fn match_compound_assignment(&self) -> bool {
+        matches!(
+            self.current.as_ref().unwrap().token_type,
+            TokenType::PlusEqual
+                | TokenType::MinusEqual
+                | TokenType::StarEqual
+                | TokenType::SlashEqual
+        )
+    }
+
+    /// Any operator which needs a variable on its left side
+    fn match_assignment_operator(&self) -> bool {
+        self.match_token_type(TokenType::Equal)
+            || self.match_token_type(TokenType::PlusPlus)
+            || self.match_token_type(TokenType::MinusMinus)
+            || self.match_compound_assignment()
+    }
+}
+
+fn constant_assignment_error(name: &Token) -> InterpretError {
+    error_report::report_error(name, "Can't assign to a constant.");
+    InterpretError::CompileError
+}
+
+/// Scan the rest of a string after its Interpolation token, including the interpolated
+/// expressions and the strings in them, the way the parser resumes it after each `}`.
+/// Returns false if the source ends before the string does.
+fn skip_interpolation(source: &mut Source) -> bool {
+    // braces opened inside the current interpolated expression
+    let mut depth = 0;
+    loop {
+        match scan::scan_token(source).token_type {
+            TokenType::Interpolation if skip_interpolation(source) => (),
+            TokenType::EOF | TokenType::Error | TokenType::Interpolation => return false,
+            TokenType::LeftBrace => depth += 1,
+            TokenType::RightBrace if depth > 0 => depth -= 1,
+            TokenType::RightBrace => match scan::continue_string(source).token_type {
+                TokenType::LString => return true,
+                TokenType::Interpolation => (),
+                _ => return false,
+            },
+            _ => (),
+        }
+    }
+}
+
+/// Patterns of each case of a match statement, scanned from the first token after `{`.
+/// Returns None if any pattern is not an integer literal or a case has a guard.
+fn scan_cases(source: &mut Source, mut token: Token) -> Option<Vec<Vec<i64>>> {
+    let mut cases = Vec::new();
+    let mut depth = 0;
+    let mut has_default = false;
+    loop {
+        match token.token_type {
+            TokenType::Interpolation if skip_interpolation(source) => (),
+            TokenType::EOF | TokenType::Error | TokenType::Interpolation => return None,
+            TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket => depth += 1,
+            TokenType::RightParen | TokenType::RightBrace | TokenType::RightBracket => {
+                if depth == 0 {
+                    break;
+                }
+                depth -= 1;
+            }
+            TokenType::Default if depth == 0 => has_default = true,
+            // leave the error to be reported by the ordinary parse
+            TokenType::Case if depth == 0 && has_default => return None,
+            TokenType::Case if depth == 0 => {
+                let mut patterns = Vec::new();
+                loop {
+                    token = scan::scan_token(source);
+                    let negative = token.token_type == TokenType::Minus;
+                    if negative {
+                        token = scan::scan_token(source);
+                    }
+                    if token.token_type != TokenType::Number {
+                        return None;
+                    }
+                    let pattern: i64 = token.lexeme.parse().ok()?;
+                    patterns.push(if negative { -pattern } else { pattern });
+                    token = scan::scan_token(source);
+                    match token.token_type {
+                        TokenType::Comma => continue,
+                        TokenType::Arrow => break,
+                        _ => return None,
+                    }
+                }
+                cases.push(patterns);
+            }
+            _ => (),
+        }
+        token = scan::scan_token(source);
+    }
+    Some(cases)
 }
 
 #[cfg(test)]
@@ -607,6 +1941,7 @@ mod tests {
     use super::*;
 
     #[test]
+    #[allow(clippy::let_unit_value)]
     fn test_advance() {
         let source = Source::new("1 + 1".to_string());
         let compiler = Compiler::new(FunctionType::Script);
@@ -616,6 +1951,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::let_unit_value)]
     fn test_expression() {
         let source = Source::new("1 + 1".to_string());
         let compiler = Compiler::new(FunctionType::Script);
@@ -634,4 +1970,68 @@ mod tests {
         let result = parser.expression();
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_script_function() {
+        let script = crate::compiler::compile("var f = fun () {};", false).unwrap();
+        assert!(script.script);
+        let f = script
+            .chunk
+            .constants
+            .iter()
+            .find_map(|constant| match constant {
+                Value::Obj(Obj::Function(function)) if !function.script => Some(function),
+                _ => None,
+            });
+        assert!(f.is_some());
+    }
+
+    #[test]
+    fn test_strip_asserts() {
+        let source = "fun g() { assert (yield 1) == nil, \"message\"; }";
+        let script = crate::compiler::compile(source, true).unwrap();
+        let g = script
+            .chunk
+            .constants
+            .iter()
+            .find_map(|constant| match constant {
+                Value::Obj(Obj::Function(function)) if function.name == "g" => Some(function),
+                _ => None,
+            })
+            .unwrap();
+        assert!(!g.generator);
+        assert!(g.chunk.constants.is_empty());
+    }
+
+    #[test]
+    fn test_jump_table_cases() {
+        let jump_table_cases = |cases: &str| {
+            let source = Source::new(cases.to_string());
+            let compiler = Compiler::new(FunctionType::Script);
+            let mut parser = Parser::new(source, compiler);
+            parser.advance().unwrap();
+            parser.jump_table_cases()
+        };
+        assert_eq!(
+            jump_table_cases("case 1, -1 => a; case 0 => { b; } default => c; }"),
+            Some(vec![vec![1, -1], vec![0]])
+        );
+        // too few patterns
+        assert_eq!(jump_table_cases("case 1 => a; case 2 => b; }"), None);
+        // too sparse
+        assert_eq!(jump_table_cases("case 1, 2 => a; case 100 => b; }"), None);
+        // not integer literals
+        assert_eq!(jump_table_cases("case 1, 2 => a; case 3.5 => b; }"), None);
+        assert_eq!(jump_table_cases("case 1, 2 => a; case x => b; }"), None);
+        // guard
+        assert_eq!(
+            jump_table_cases("case 1, 2 => a; case 3 if x => b; }"),
+            None
+        );
+        // interpolated strings in the bodies
+        assert_eq!(
+            jump_table_cases("case 1 => \"${a}\"; case 2, 3 => \"${ {\"k\": \"${b}\"} }\"; }"),
+            Some(vec![vec![1], vec![2, 3]])
+        );
+    }
 }
@@ -4,13 +4,20 @@ use crate::token::TokenType;
 pub enum Precedence {
     None,
     Assignment,
+    Conditional,
+    Coalesce,
     Or,
     And,
     Equality,
     Comparison,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shift,
     Term,
     Factor,
     Unary,
+    Exponent,
     Call,
     Primary,
 }
@@ -19,14 +26,21 @@ impl Precedence {
     pub fn next(&self) -> Self {
         match self {
             Self::None => Self::Assignment,
-            Self::Assignment => Self::Or,
+            Self::Assignment => Self::Conditional,
+            Self::Conditional => Self::Coalesce,
+            Self::Coalesce => Self::Or,
             Self::Or => Self::And,
             Self::And => Self::Equality,
             Self::Equality => Self::Comparison,
-            Self::Comparison => Self::Term,
+            Self::Comparison => Self::BitOr,
+            Self::BitOr => Self::BitXor,
+            Self::BitXor => Self::BitAnd,
+            Self::BitAnd => Self::Shift,
+            Self::Shift => Self::Term,
             Self::Term => Self::Factor,
             Self::Factor => Self::Unary,
-            Self::Unary => Self::Call,
+            Self::Unary => Self::Exponent,
+            Self::Exponent => Self::Call,
             Self::Call => Self::Primary,
             Self::Primary => panic!("There is no next precedence"),
         }
@@ -51,7 +65,20 @@ pub enum ParseFn {
     String,
     And,
     Or,
+    Coalesce,
     Call,
+    OptionalCall,
+    Dot,
+    This,
+    Super,
+    List,
+    Subscript,
+    Map,
+    Interpolation,
+    Increment,
+    Conditional,
+    Lambda,
+    Yield,
 }
 
 pub fn get_rule(operator_type: &TokenType) -> ParseRule {
@@ -67,7 +94,7 @@ pub fn get_rule(operator_type: &TokenType) -> ParseRule {
             precedence: Precedence::None,
         },
         TokenType::LeftBrace => ParseRule {
-            prefix: None,
+            prefix: Some(ParseFn::Map),
             infix: None,
             precedence: Precedence::None,
         },
@@ -76,16 +103,31 @@ pub fn get_rule(operator_type: &TokenType) -> ParseRule {
             infix: None,
             precedence: Precedence::None,
         },
-        TokenType::Comma => ParseRule {
+        TokenType::LeftBracket => ParseRule {
+            prefix: Some(ParseFn::List),
+            infix: Some(ParseFn::Subscript),
+            precedence: Precedence::Call,
+        },
+        TokenType::RightBracket => ParseRule {
             prefix: None,
             infix: None,
             precedence: Precedence::None,
         },
-        TokenType::Dot => ParseRule {
+        TokenType::Colon => ParseRule {
             prefix: None,
             infix: None,
             precedence: Precedence::None,
         },
+        TokenType::Comma => ParseRule {
+            prefix: None,
+            infix: None,
+            precedence: Precedence::None,
+        },
+        TokenType::Dot => ParseRule {
+            prefix: None,
+            infix: Some(ParseFn::Dot),
+            precedence: Precedence::Call,
+        },
         TokenType::Minus => ParseRule {
             prefix: Some(ParseFn::Unary),
             infix: Some(ParseFn::Binary),
@@ -111,6 +153,79 @@ pub fn get_rule(operator_type: &TokenType) -> ParseRule {
             infix: Some(ParseFn::Binary),
             precedence: Precedence::Factor,
         },
+        TokenType::PlusPlus | TokenType::MinusMinus => ParseRule {
+            prefix: Some(ParseFn::Increment),
+            infix: None,
+            precedence: Precedence::None,
+        },
+        TokenType::PlusEqual
+        | TokenType::MinusEqual
+        | TokenType::StarEqual
+        | TokenType::SlashEqual => ParseRule {
+            prefix: None,
+            infix: None,
+            precedence: Precedence::None,
+        },
+        TokenType::Arrow | TokenType::DotDotDot => ParseRule {
+            prefix: None,
+            infix: None,
+            precedence: Precedence::None,
+        },
+        TokenType::Question => ParseRule {
+            prefix: None,
+            infix: Some(ParseFn::Conditional),
+            precedence: Precedence::Conditional,
+        },
+        TokenType::QuestionQuestion => ParseRule {
+            prefix: None,
+            infix: Some(ParseFn::Coalesce),
+            precedence: Precedence::Coalesce,
+        },
+        TokenType::QuestionDot => ParseRule {
+            prefix: None,
+            infix: Some(ParseFn::OptionalCall),
+            precedence: Precedence::Call,
+        },
+        TokenType::Percent => ParseRule {
+            prefix: None,
+            infix: Some(ParseFn::Binary),
+            precedence: Precedence::Factor,
+        },
+        TokenType::StarStar => ParseRule {
+            prefix: None,
+            infix: Some(ParseFn::Binary),
+            precedence: Precedence::Exponent,
+        },
+        TokenType::Ampersand => ParseRule {
+            prefix: None,
+            infix: Some(ParseFn::Binary),
+            precedence: Precedence::BitAnd,
+        },
+        TokenType::Pipe => ParseRule {
+            prefix: None,
+            infix: Some(ParseFn::Binary),
+            precedence: Precedence::BitOr,
+        },
+        TokenType::Caret => ParseRule {
+            prefix: None,
+            infix: Some(ParseFn::Binary),
+            precedence: Precedence::BitXor,
+        },
+        TokenType::Tilde => ParseRule {
+            prefix: Some(ParseFn::Unary),
+            infix: None,
+            precedence: Precedence::None,
+        },
+        TokenType::LessLess => ParseRule {
+            prefix: None,
+            infix: Some(ParseFn::Binary),
+            precedence: Precedence::Shift,
+        },
+        TokenType::GreaterGreater => ParseRule {
+            prefix: None,
+            infix: Some(ParseFn::Binary),
+            precedence: Precedence::Shift,
+        },
         TokenType::Bang => ParseRule {
             prefix: Some(ParseFn::Unary),
             infix: None,
@@ -161,6 +276,11 @@ pub fn get_rule(operator_type: &TokenType) -> ParseRule {
             infix: None,
             precedence: Precedence::None,
         },
+        TokenType::Interpolation => ParseRule {
+            prefix: Some(ParseFn::Interpolation),
+            infix: None,
+            precedence: Precedence::None,
+        },
         TokenType::Number => ParseRule {
             prefix: Some(ParseFn::Number),
             infix: None,
@@ -171,6 +291,35 @@ pub fn get_rule(operator_type: &TokenType) -> ParseRule {
             infix: Some(ParseFn::And),
             precedence: Precedence::And,
         },
+        TokenType::Break => ParseRule {
+            prefix: None,
+            infix: None,
+            precedence: Precedence::None,
+        },
+        TokenType::Assert
+        | TokenType::Throw
+        | TokenType::Try
+        | TokenType::Catch
+        | TokenType::Finally
+        | TokenType::Import
+        | TokenType::Const
+        | TokenType::Match
+        | TokenType::Case
+        | TokenType::Default => ParseRule {
+            prefix: None,
+            infix: None,
+            precedence: Precedence::None,
+        },
+        TokenType::Yield => ParseRule {
+            prefix: Some(ParseFn::Yield),
+            infix: None,
+            precedence: Precedence::None,
+        },
+        TokenType::Continue => ParseRule {
+            prefix: None,
+            infix: None,
+            precedence: Precedence::None,
+        },
         TokenType::Class => ParseRule {
             prefix: None,
             infix: None,
@@ -192,7 +341,7 @@ pub fn get_rule(operator_type: &TokenType) -> ParseRule {
             precedence: Precedence::None,
         },
         TokenType::Fun => ParseRule {
-            prefix: None,
+            prefix: Some(ParseFn::Lambda),
             infix: None,
             precedence: Precedence::None,
         },
@@ -222,12 +371,12 @@ pub fn get_rule(operator_type: &TokenType) -> ParseRule {
             precedence: Precedence::None,
         },
         TokenType::Super => ParseRule {
-            prefix: None,
+            prefix: Some(ParseFn::Super),
             infix: None,
             precedence: Precedence::None,
         },
         TokenType::This => ParseRule {
-            prefix: None,
+            prefix: Some(ParseFn::This),
             infix: None,
             precedence: Precedence::None,
         },
@@ -263,6 +412,14 @@ pub fn get_rule(operator_type: &TokenType) -> ParseRule {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_next() {
+        assert_eq!(Precedence::Factor.next(), Precedence::Unary);
+        assert_eq!(Precedence::Unary.next(), Precedence::Exponent);
+        assert_eq!(Precedence::Assignment.next(), Precedence::Conditional);
+        assert_eq!(Precedence::Conditional.next(), Precedence::Coalesce);
+    }
+
     #[test]
     fn test_get_rule() {
         let expected_parse_rule = ParseRule {
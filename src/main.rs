@@ -8,14 +8,19 @@ use vm::{interpret, InterpretError, VM};
 mod chunk;
 mod compiler;
 mod disassembler;
+mod native;
 mod scan;
 mod token;
 mod value;
 mod vm;
 
-fn repl() {
+/// Flag to compile scripts without assert statements
+const STRIP_ASSERTS_FLAG: &str = "--strip-asserts";
+
+fn repl(strip_asserts: bool) {
     let stdin = io::stdin();
     let mut vm = VM::new();
+    vm.set_strip_asserts(strip_asserts);
     loop {
         print!("> ");
         let mut buffer = String::new();
@@ -28,13 +33,15 @@ fn repl() {
             exit(0)
         }
         let line = buffer.trim().to_string();
-        interpret(&mut vm, &line);
+        let _ = interpret(&mut vm, &line);
     }
 }
 
-fn run_file(path: &Path) {
+fn run_file(path: &Path, strip_asserts: bool) {
     let source = fs::read_to_string(path).unwrap();
     let mut vm = VM::new();
+    vm.set_main_path(path);
+    vm.set_strip_asserts(strip_asserts);
     if let Err(e) = interpret(&mut vm, &source) {
         match e {
             InterpretError::CompileError => exit(65),
@@ -44,15 +51,17 @@ fn run_file(path: &Path) {
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    let strip_asserts = args.iter().any(|arg| arg == STRIP_ASSERTS_FLAG);
+    args.retain(|arg| arg != STRIP_ASSERTS_FLAG);
     let args_length = args.len();
     if args_length == 1 {
-        repl();
+        repl(strip_asserts);
     } else if args_length == 2 {
         let path = Path::new(&args[1]);
-        run_file(path);
+        run_file(path, strip_asserts);
     } else {
-        eprintln!("Usage: brlox [path]");
+        eprintln!("Usage: brlox [{STRIP_ASSERTS_FLAG}] [path]");
         exit(64)
     }
 }
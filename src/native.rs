@@ -0,0 +1,369 @@
+use std::{cell::RefCell, rc::Rc, time::SystemTime};
+
+use crate::value::{
+    object::{MapKey, NativeFunction, Obj, ObjError, ObjList, ObjMap, ObjNative, ObjRange},
+    Value,
+};
+
+pub fn clock(_: &[Value]) -> Result<Value, String> {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap();
+    Ok(Value::Number(now.as_secs_f64()))
+}
+
+/// Number of items in a list, a map or a tuple, or characters in a string
+pub fn len(args: &[Value]) -> Result<Value, String> {
+    match &args[0] {
+        Value::Obj(Obj::List(list)) => Ok(Value::Integer(list.borrow().items.len() as i64)),
+        Value::Obj(Obj::Map(map)) => Ok(Value::Integer(map.borrow().entries.len() as i64)),
+        Value::Obj(Obj::Tuple(tuple)) => Ok(Value::Integer(tuple.items.len() as i64)),
+        Value::LString(string) => Ok(Value::Integer(string.chars().count() as i64)),
+        _ => Err("len() expects a list, a map, a tuple or a string.".to_string()),
+    }
+}
+
+/// Append a value to the end of a list and return the list
+pub fn push(args: &[Value]) -> Result<Value, String> {
+    let list = as_list(&args[0], "push")?;
+    list.borrow_mut().items.push(args[1].clone());
+    Ok(args[0].clone())
+}
+
+/// Remove the last item of a list and return it
+pub fn pop(args: &[Value]) -> Result<Value, String> {
+    let list = as_list(&args[0], "pop")?;
+    let item = list.borrow_mut().items.pop();
+    item.ok_or_else(|| "Can't pop from an empty list.".to_string())
+}
+
+/// List of the keys of a map in sorted order
+pub fn keys(args: &[Value]) -> Result<Value, String> {
+    let map = as_map(&args[0], "keys")?;
+    let keys = map
+        .borrow()
+        .sorted_entries()
+        .into_iter()
+        .map(|(key, _)| key.to_value())
+        .collect();
+    Ok(new_list(keys))
+}
+
+/// List of the values of a map in the order of `keys()`
+pub fn values(args: &[Value]) -> Result<Value, String> {
+    let map = as_map(&args[0], "values")?;
+    let values = map
+        .borrow()
+        .sorted_entries()
+        .into_iter()
+        .map(|(_, value)| value.clone())
+        .collect();
+    Ok(new_list(values))
+}
+
+/// Whether a map contains the key
+pub fn has(args: &[Value]) -> Result<Value, String> {
+    let map = as_map(&args[0], "has")?;
+    let key = as_map_key(&args[1])?;
+    Ok(Value::Bool(map.borrow().entries.contains_key(&key)))
+}
+
+/// Remove the key from a map and return its value, or nil if it didn't exist
+pub fn remove(args: &[Value]) -> Result<Value, String> {
+    let map = as_map(&args[0], "remove")?;
+    let key = as_map_key(&args[1])?;
+    let value = map.borrow_mut().entries.remove(&key);
+    Ok(value.unwrap_or(Value::Nil))
+}
+
+/// Error value with the given message to be thrown
+pub fn error(args: &[Value]) -> Result<Value, String> {
+    let message = match &args[0] {
+        Value::LString(message) => message.clone(),
+        value => value.to_string(),
+    };
+    let error = ObjError::new(message);
+    Ok(Value::Obj(Obj::Error(Rc::new(RefCell::new(error)))))
+}
+
+/// Convert a number or a string into an integer, truncating floats toward zero
+pub fn int(args: &[Value]) -> Result<Value, String> {
+    match &args[0] {
+        Value::Integer(integer) => Ok(Value::Integer(*integer)),
+        Value::Number(number) => Value::Number(number.trunc())
+            .as_integer()
+            .map(Value::Integer)
+            .ok_or_else(|| format!("Can't convert {number} to an integer.")),
+        Value::LString(string) => string
+            .trim()
+            .parse::<i64>()
+            .map(Value::Integer)
+            .map_err(|_| format!("Can't convert '{string}' to an integer.")),
+        _ => Err("int() expects a number or a string.".to_string()),
+    }
+}
+
+/// Convert a number or a string into a float
+pub fn float(args: &[Value]) -> Result<Value, String> {
+    match &args[0] {
+        Value::Integer(integer) => Ok(Value::Number(*integer as f64)),
+        Value::Number(number) => Ok(Value::Number(*number)),
+        Value::LString(string) => string
+            .trim()
+            .parse::<f64>()
+            .map(Value::Number)
+            .map_err(|_| format!("Can't convert '{string}' to a float.")),
+        _ => Err("float() expects a number or a string.".to_string()),
+    }
+}
+
+/// Integers for `for ... in` loops like `range(end)`, `range(start, end)` or `range(start, end, step)`
+pub fn range(args: &[Value]) -> Result<Value, String> {
+    let integers = args
+        .iter()
+        .map(|arg| arg.as_integer())
+        .collect::<Option<Vec<i64>>>()
+        .ok_or_else(|| "range() expects integers.".to_string())?;
+    let (start, end, step) = match integers[..] {
+        [end] => (0, end, 1),
+        [start, end] => (start, end, 1),
+        [start, end, step] => (start, end, step),
+        _ => unreachable!(),
+    };
+    if step == 0 {
+        return Err("range() step can't be zero.".to_string());
+    }
+    Ok(Value::Obj(Obj::Range(ObjRange { start, end, step })))
+}
+
+/// Built-in method of strings with the given name.
+/// The string itself is passed as the first argument.
+pub fn string_method(name: &str) -> Option<ObjNative> {
+    let (native_function, arity): (NativeFunction, usize) = match name {
+        "len" => (len, 0),
+        "upper" => (string_upper, 0),
+        "lower" => (string_lower, 0),
+        "split" => (string_split, 1),
+        "find" => (string_find, 1),
+        "replace" => (string_replace, 2),
+        "trim" => (string_trim, 0),
+        "starts_with" => (string_starts_with, 1),
+        "ends_with" => (string_ends_with, 1),
+        _ => return None,
+    };
+    Some(ObjNative::new(native_function, arity..=arity))
+}
+
+fn string_upper(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::LString(as_string(&args[0], "upper")?.to_uppercase()))
+}
+
+fn string_lower(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::LString(as_string(&args[0], "lower")?.to_lowercase()))
+}
+
+/// List of the parts between separators, or of the characters if the separator is empty
+fn string_split(args: &[Value]) -> Result<Value, String> {
+    let string = as_string(&args[0], "split")?;
+    let separator = as_string(&args[1], "split")?;
+    let parts = if separator.is_empty() {
+        string
+            .chars()
+            .map(|c| Value::LString(c.to_string()))
+            .collect()
+    } else {
+        string
+            .split(separator)
+            .map(|part| Value::LString(part.to_string()))
+            .collect()
+    };
+    Ok(new_list(parts))
+}
+
+/// Character index of the first occurrence of the substring, or nil if it doesn't occur
+fn string_find(args: &[Value]) -> Result<Value, String> {
+    let string = as_string(&args[0], "find")?;
+    let substring = as_string(&args[1], "find")?;
+    let index = string
+        .find(substring)
+        .map(|byte_index| string[..byte_index].chars().count() as i64);
+    Ok(index.map_or(Value::Nil, Value::Integer))
+}
+
+fn string_replace(args: &[Value]) -> Result<Value, String> {
+    let string = as_string(&args[0], "replace")?;
+    let from = as_string(&args[1], "replace")?;
+    let to = as_string(&args[2], "replace")?;
+    if from.is_empty() {
+        return Err("replace() can't replace an empty string.".to_string());
+    }
+    Ok(Value::LString(string.replace(from, to)))
+}
+
+fn string_trim(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::LString(
+        as_string(&args[0], "trim")?.trim().to_string(),
+    ))
+}
+
+fn string_starts_with(args: &[Value]) -> Result<Value, String> {
+    let string = as_string(&args[0], "starts_with")?;
+    let prefix = as_string(&args[1], "starts_with")?;
+    Ok(Value::Bool(string.starts_with(prefix)))
+}
+
+fn string_ends_with(args: &[Value]) -> Result<Value, String> {
+    let string = as_string(&args[0], "ends_with")?;
+    let suffix = as_string(&args[1], "ends_with")?;
+    Ok(Value::Bool(string.ends_with(suffix)))
+}
+
+fn new_list(items: Vec<Value>) -> Value {
+    Value::Obj(Obj::List(Rc::new(RefCell::new(ObjList::new(items)))))
+}
+
+fn as_map<'a>(value: &'a Value, function_name: &str) -> Result<&'a Rc<RefCell<ObjMap>>, String> {
+    match value {
+        Value::Obj(Obj::Map(map)) => Ok(map),
+        _ => Err(format!("{function_name}() expects a map.")),
+    }
+}
+
+fn as_map_key(value: &Value) -> Result<MapKey, String> {
+    MapKey::from_value(value)
+        .ok_or_else(|| "Map keys must be strings, numbers, booleans or nil.".to_string())
+}
+
+fn as_string<'a>(value: &'a Value, function_name: &str) -> Result<&'a str, String> {
+    match value {
+        Value::LString(string) => Ok(string),
+        _ => Err(format!("{function_name}() expects a string.")),
+    }
+}
+
+fn as_list<'a>(value: &'a Value, function_name: &str) -> Result<&'a Rc<RefCell<ObjList>>, String> {
+    match value {
+        Value::Obj(Obj::List(list)) => Ok(list),
+        _ => Err(format!("{function_name}() expects a list.")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_map(entries: Vec<(&str, Value)>) -> Value {
+        let mut map = ObjMap::new();
+        for (key, value) in entries {
+            map.entries.insert(MapKey::LString(key.to_string()), value);
+        }
+        Value::Obj(Obj::Map(Rc::new(RefCell::new(map))))
+    }
+
+    #[test]
+    fn test_len() {
+        let list = new_list(vec![Value::Nil, Value::Nil]);
+        assert_eq!(len(&[list]).unwrap().as_number(), 2.0);
+        let string = Value::LString("あいう".to_string());
+        assert_eq!(len(&[string]).unwrap().as_number(), 3.0);
+        assert!(len(&[Value::Nil]).is_err());
+    }
+
+    #[test]
+    fn test_int() {
+        assert!(matches!(
+            int(&[Value::Number(-2.7)]),
+            Ok(Value::Integer(-2))
+        ));
+        let string = Value::LString(" 42 ".to_string());
+        assert!(matches!(int(&[string]), Ok(Value::Integer(42))));
+        assert!(int(&[Value::Number(f64::NAN)]).is_err());
+        assert!(int(&[Value::LString("1.5".to_string())]).is_err());
+        assert!(int(&[Value::Nil]).is_err());
+    }
+
+    #[test]
+    fn test_float() {
+        assert!(matches!(float(&[Value::Integer(3)]), Ok(Value::Number(number)) if number == 3.0));
+        let string = Value::LString("1.5".to_string());
+        assert_eq!(float(&[string]).unwrap().as_number(), 1.5);
+        assert!(float(&[Value::LString("a".to_string())]).is_err());
+    }
+
+    #[test]
+    fn test_string_method() {
+        let call = |name: &str, args: &[Value]| {
+            (string_method(name).unwrap().native_function)(args).map(|value| value.to_string())
+        };
+        let string = || Value::LString(" añb,c ".to_string());
+        let arg = |arg: &str| Value::LString(arg.to_string());
+        assert_eq!(call("len", &[string()]).unwrap(), "7");
+        assert_eq!(call("upper", &[string()]).unwrap(), " AÑB,C ");
+        assert_eq!(
+            call("split", &[string(), arg(",")]).unwrap(),
+            "[\" añb\", \"c \"]"
+        );
+        assert_eq!(
+            call("split", &[arg("añ"), arg("")]).unwrap(),
+            "[\"a\", \"ñ\"]"
+        );
+        assert_eq!(call("find", &[string(), arg("b")]).unwrap(), "3");
+        assert_eq!(call("find", &[string(), arg("x")]).unwrap(), "nil");
+        assert_eq!(
+            call("replace", &[string(), arg(","), arg("")]).unwrap(),
+            " añbc "
+        );
+        assert!(call("replace", &[string(), arg(""), arg("x")]).is_err());
+        assert_eq!(call("trim", &[string()]).unwrap(), "añb,c");
+        assert_eq!(call("starts_with", &[string(), arg(" a")]).unwrap(), "true");
+        assert!(call("find", &[string(), Value::Nil]).is_err());
+        assert!(string_method("missing").is_none());
+    }
+
+    #[test]
+    fn test_range() {
+        let range = |args: &[i64]| {
+            let args: Vec<Value> = args.iter().map(|arg| Value::Integer(*arg)).collect();
+            super::range(&args).map(|value| value.to_string())
+        };
+        assert_eq!(range(&[3]).unwrap(), "range(0, 3, 1)");
+        assert_eq!(range(&[5, 1, -2]).unwrap(), "range(5, 1, -2)");
+        assert!(range(&[0, 1, 0]).is_err());
+        assert!(super::range(&[Value::Number(0.5)]).is_err());
+    }
+
+    #[test]
+    fn test_push_and_pop() {
+        let list = new_list(Vec::new());
+        let args = [list, Value::Number(1.0)];
+        push(&args).unwrap();
+        assert_eq!(len(&args[..1]).unwrap().as_number(), 1.0);
+        assert_eq!(pop(&args[..1]).unwrap().as_number(), 1.0);
+        assert!(pop(&args[..1]).is_err());
+    }
+
+    #[test]
+    fn test_keys_and_values() {
+        let map = new_map(vec![("b", Value::Number(2.0)), ("a", Value::Number(1.0))]);
+        let args = [map];
+        assert_eq!(keys(&args).unwrap().to_string(), "[\"a\", \"b\"]");
+        assert_eq!(values(&args).unwrap().to_string(), "[1, 2]");
+    }
+
+    #[test]
+    fn test_has_and_remove() {
+        let map = new_map(vec![("a", Value::Number(1.0))]);
+        let args = [map, Value::LString("a".to_string())];
+        assert!(has(&args).unwrap().values_equal(Value::Bool(true)));
+        assert_eq!(remove(&args).unwrap().as_number(), 1.0);
+        assert!(has(&args).unwrap().values_equal(Value::Bool(false)));
+        assert!(remove(&args).unwrap().values_equal(Value::Nil));
+    }
+
+    #[test]
+    fn test_error() {
+        let message = Value::LString("failed".to_string());
+        let error = error(&[message]).unwrap();
+        assert_eq!(error.to_string(), "Error: failed");
+    }
+}
@@ -1,8 +1,9 @@
 use crate::token::{Token, TokenType};
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Source {
     pub text: String,
+    /// start and current are byte offsets in text
     pub start: usize,
     pub current: usize,
     pub line: usize,
@@ -17,10 +18,36 @@ impl Source {
             line: 1,
         }
     }
+
+    pub fn position(&self) -> Position {
+        Position {
+            start: self.start,
+            current: self.current,
+            line: self.line,
+        }
+    }
+
+    /// Go back to the position to scan the same tokens again
+    pub fn restore(&mut self, position: Position) {
+        self.start = position.start;
+        self.current = position.current;
+        self.line = position.line;
+    }
+}
+
+/// Where the scanner is in the source, which is saved to scan tokens ahead
+/// without copying the text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    start: usize,
+    current: usize,
+    line: usize,
 }
 
 pub fn scan_token(source: &mut Source) -> Token {
-    skip_white_space(source);
+    if let Err(token) = skip_white_space(source) {
+        return token;
+    }
     source.start = source.current;
 
     if is_at_end(source) {
@@ -41,13 +68,73 @@ pub fn scan_token(source: &mut Source) -> Token {
         ')' => make_token(source, TokenType::RightParen),
         '{' => make_token(source, TokenType::LeftBrace),
         '}' => make_token(source, TokenType::RightBrace),
+        '[' => make_token(source, TokenType::LeftBracket),
+        ']' => make_token(source, TokenType::RightBracket),
         ';' => make_token(source, TokenType::Semicolon),
         ',' => make_token(source, TokenType::Comma),
-        '.' => make_token(source, TokenType::Dot),
-        '-' => make_token(source, TokenType::Minus),
-        '+' => make_token(source, TokenType::Plus),
-        '*' => make_token(source, TokenType::Star),
-        '/' => make_token(source, TokenType::Slash),
+        ':' => make_token(source, TokenType::Colon),
+        '?' => {
+            let token_type = if match_char(source, '?') {
+                TokenType::QuestionQuestion
+            } else if match_char(source, '.') {
+                TokenType::QuestionDot
+            } else {
+                TokenType::Question
+            };
+            make_token(source, token_type)
+        }
+        '.' => {
+            if peek(source) == '.' && peek_next(source) == '.' {
+                advance(source);
+                advance(source);
+                make_token(source, TokenType::DotDotDot)
+            } else {
+                make_token(source, TokenType::Dot)
+            }
+        }
+        '-' => {
+            let token_type = if match_char(source, '=') {
+                TokenType::MinusEqual
+            } else if match_char(source, '-') {
+                TokenType::MinusMinus
+            } else {
+                TokenType::Minus
+            };
+            make_token(source, token_type)
+        }
+        '+' => {
+            let token_type = if match_char(source, '=') {
+                TokenType::PlusEqual
+            } else if match_char(source, '+') {
+                TokenType::PlusPlus
+            } else {
+                TokenType::Plus
+            };
+            make_token(source, token_type)
+        }
+        '*' => {
+            let token_type = if match_char(source, '*') {
+                TokenType::StarStar
+            } else if match_char(source, '=') {
+                TokenType::StarEqual
+            } else {
+                TokenType::Star
+            };
+            make_token(source, token_type)
+        }
+        '/' => {
+            let token_type = if match_char(source, '=') {
+                TokenType::SlashEqual
+            } else {
+                TokenType::Slash
+            };
+            make_token(source, token_type)
+        }
+        '%' => make_token(source, TokenType::Percent),
+        '&' => make_token(source, TokenType::Ampersand),
+        '|' => make_token(source, TokenType::Pipe),
+        '^' => make_token(source, TokenType::Caret),
+        '~' => make_token(source, TokenType::Tilde),
         '"' => string(source),
         '!' => {
             let token_type = if match_char(source, '=') {
@@ -60,6 +147,8 @@ pub fn scan_token(source: &mut Source) -> Token {
         '=' => {
             let token_type = if match_char(source, '=') {
                 TokenType::EqualEqual
+            } else if match_char(source, '>') {
+                TokenType::Arrow
             } else {
                 TokenType::Equal
             };
@@ -68,6 +157,8 @@ pub fn scan_token(source: &mut Source) -> Token {
         '<' => {
             let token_type = if match_char(source, '=') {
                 TokenType::LessEqual
+            } else if match_char(source, '<') {
+                TokenType::LessLess
             } else {
                 TokenType::Less
             };
@@ -76,6 +167,8 @@ pub fn scan_token(source: &mut Source) -> Token {
         '>' => {
             let token_type = if match_char(source, '=') {
                 TokenType::GreaterEqual
+            } else if match_char(source, '>') {
+                TokenType::GreaterGreater
             } else {
                 TokenType::Greater
             };
@@ -109,29 +202,61 @@ fn identifier(source: &mut Source) -> Token {
 }
 
 fn identifier_type(source: &Source) -> TokenType {
-    match nth_char(source.text.clone(), source.start) {
-        'a' => return check_keyword(source, 1, "nd", TokenType::And),
-        'c' => return check_keyword(source, 1, "lass", TokenType::Class),
-        'e' => return check_keyword(source, 1, "lse", TokenType::Else),
-        'f' => match nth_char(source.text.clone(), source.start + 1) {
-            'a' => return check_keyword(source, 2, "lse", TokenType::False),
-            'o' => return check_keyword(source, 2, "r", TokenType::For),
-            'u' => return check_keyword(source, 2, "n", TokenType::Fun),
+    match nth_char(&source.text, source.start) {
+        'a' => match nth_char(&source.text, source.start + 1) {
+            'n' => check_keyword(source, 2, "d", TokenType::And),
+            's' => check_keyword(source, 2, "sert", TokenType::Assert),
             _ => TokenType::Identifier,
         },
-        'i' => return check_keyword(source, 1, "f", TokenType::If),
-        'n' => return check_keyword(source, 1, "il", TokenType::Nil),
-        'o' => return check_keyword(source, 1, "r", TokenType::Or),
-        'p' => return check_keyword(source, 1, "rint", TokenType::Print),
-        'r' => return check_keyword(source, 1, "eturn", TokenType::Return),
-        's' => return check_keyword(source, 1, "uper", TokenType::Super),
-        't' => match nth_char(source.text.clone(), source.start + 1) {
-            'h' => return check_keyword(source, 2, "is", TokenType::This),
-            'r' => return check_keyword(source, 2, "ue", TokenType::True),
+        'b' => check_keyword(source, 1, "reak", TokenType::Break),
+        'c' => match nth_char(&source.text, source.start + 1) {
+            'a' => match nth_char(&source.text, source.start + 2) {
+                's' => check_keyword(source, 3, "e", TokenType::Case),
+                _ => check_keyword(source, 2, "tch", TokenType::Catch),
+            },
+            'l' => check_keyword(source, 2, "ass", TokenType::Class),
+            'o' => match nth_char(&source.text, source.start + 3) {
+                's' => check_keyword(source, 2, "nst", TokenType::Const),
+                _ => check_keyword(source, 2, "ntinue", TokenType::Continue),
+            },
             _ => TokenType::Identifier,
         },
-        'v' => return check_keyword(source, 1, "ar", TokenType::Var),
-        'w' => return check_keyword(source, 1, "hile", TokenType::While),
+        'd' => check_keyword(source, 1, "efault", TokenType::Default),
+        'e' => check_keyword(source, 1, "lse", TokenType::Else),
+        'f' => match nth_char(&source.text, source.start + 1) {
+            'a' => check_keyword(source, 2, "lse", TokenType::False),
+            'i' => check_keyword(source, 2, "nally", TokenType::Finally),
+            'o' => check_keyword(source, 2, "r", TokenType::For),
+            'u' => check_keyword(source, 2, "n", TokenType::Fun),
+            _ => TokenType::Identifier,
+        },
+        'i' => match nth_char(&source.text, source.start + 1) {
+            'f' => check_keyword(source, 2, "", TokenType::If),
+            'm' => check_keyword(source, 2, "port", TokenType::Import),
+            _ => TokenType::Identifier,
+        },
+        'm' => check_keyword(source, 1, "atch", TokenType::Match),
+        'n' => check_keyword(source, 1, "il", TokenType::Nil),
+        'o' => check_keyword(source, 1, "r", TokenType::Or),
+        'p' => check_keyword(source, 1, "rint", TokenType::Print),
+        'r' => check_keyword(source, 1, "eturn", TokenType::Return),
+        's' => check_keyword(source, 1, "uper", TokenType::Super),
+        't' => match nth_char(&source.text, source.start + 1) {
+            'h' => match nth_char(&source.text, source.start + 2) {
+                'i' => check_keyword(source, 3, "s", TokenType::This),
+                'r' => check_keyword(source, 3, "ow", TokenType::Throw),
+                _ => TokenType::Identifier,
+            },
+            'r' => match nth_char(&source.text, source.start + 2) {
+                'u' => check_keyword(source, 3, "e", TokenType::True),
+                'y' => check_keyword(source, 3, "", TokenType::Try),
+                _ => TokenType::Identifier,
+            },
+            _ => TokenType::Identifier,
+        },
+        'v' => check_keyword(source, 1, "ar", TokenType::Var),
+        'w' => check_keyword(source, 1, "hile", TokenType::While),
+        'y' => check_keyword(source, 1, "ield", TokenType::Yield),
         _ => TokenType::Identifier,
     }
 }
@@ -148,10 +273,11 @@ fn check_keyword(source: &Source, start: usize, rest: &str, token_type: TokenTyp
     if source.text[head_of_token..tail_of_token].ne(rest) {
         return TokenType::Identifier;
     }
-    return token_type;
+    token_type
 }
 
-fn skip_white_space(source: &mut Source) {
+/// Returns an error token if a block comment is not terminated
+fn skip_white_space(source: &mut Source) -> Result<(), Token> {
     loop {
         let c = peek(source);
         match c {
@@ -162,45 +288,91 @@ fn skip_white_space(source: &mut Source) {
                 source.line += 1;
                 advance(source);
             }
-            '/' => {
-                if peek_next(source) != '/' {
-                    return;
+            '/' => match peek_next(source) {
+                '/' => {
+                    while peek(source) != '\n' && !is_at_end(source) {
+                        advance(source);
+                    }
                 }
-                while peek(source) != '\n' && !is_at_end(source) {
+                '*' => block_comment(source)?,
+                _ => return Ok(()),
+            },
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// Skip a `/* ... */` comment, which may contain nested block comments
+fn block_comment(source: &mut Source) -> Result<(), Token> {
+    let start_line = source.line;
+    let mut depth = 0;
+    loop {
+        if is_at_end(source) {
+            return Err(error_token(start_line, "Unterminated block comment."));
+        }
+        match (peek(source), peek_next(source)) {
+            ('/', '*') => {
+                advance(source);
+                depth += 1;
+            }
+            ('*', '/') => {
+                advance(source);
+                depth -= 1;
+                if depth == 0 {
                     advance(source);
+                    return Ok(());
                 }
             }
-            _ => return,
+            ('\n', _) => source.line += 1,
+            _ => (),
         }
+        advance(source);
     }
 }
 
 fn peek(source: &Source) -> char {
-    nth_char(source.text.clone(), source.current)
+    nth_char(&source.text, source.current)
 }
 
 fn peek_next(source: &Source) -> char {
     if is_at_end(source) {
         return '\0';
     }
-    nth_char(source.text.clone(), source.current + 1)
+    nth_char(&source.text, source.current + peek(source).len_utf8())
 }
 
 fn match_char(source: &mut Source, c: char) -> bool {
     if is_at_end(source) {
         return false;
     }
-    if nth_char(source.text.clone(), source.current) != c {
+    if nth_char(&source.text, source.current) != c {
         return false;
     }
-    source.current += 1;
+    source.current += c.len_utf8();
     true
 }
 
+/// Scan a string literal after its opening quote.
+/// If the string contains `${`, the part before it is returned as an Interpolation token
+/// and the parser resumes the string with `continue_string` after the embedded expression.
 fn string(source: &mut Source) -> Token {
     while peek(source) != '"' && !is_at_end(source) {
-        if peek(source) == '\n' {
-            source.line += 1;
+        match peek(source) {
+            '\n' => source.line += 1,
+            '\\' => {
+                advance(source);
+                match read_escape(&source.text[source.current..]) {
+                    Ok((_, length)) => source.current += length,
+                    Err(message) => return error_token(source.line, message),
+                }
+                continue;
+            }
+            '$' if peek_next(source) == '{' => {
+                advance(source);
+                advance(source);
+                return make_token(source, TokenType::Interpolation);
+            }
+            _ => (),
         }
         advance(source);
     }
@@ -212,6 +384,68 @@ fn string(source: &mut Source) -> Token {
     make_token(source, TokenType::LString)
 }
 
+/// Resume scanning a string literal right after the `}` closing an interpolated expression.
+pub fn continue_string(source: &mut Source) -> Token {
+    // The lexeme starts from `}` just like it starts from `"` in the beginning of strings
+    source.start = source.current - 1;
+    string(source)
+}
+
+/// Content of a string literal token with its delimiters removed and escape sequences replaced
+pub fn string_content(token: &Token) -> String {
+    let lexeme = &token.lexeme;
+    let content = match token.token_type {
+        // `"...${`
+        TokenType::Interpolation => &lexeme[1..lexeme.len() - 2],
+        // `"..."`
+        _ => &lexeme[1..lexeme.len() - 1],
+    };
+    let mut unescaped = String::new();
+    let mut rest = content;
+    while let Some(backslash) = rest.find('\\') {
+        unescaped.push_str(&rest[..backslash]);
+        let (escaped, length) =
+            read_escape(&rest[backslash + 1..]).expect("Escape sequences are checked by scanner");
+        unescaped.push(escaped);
+        rest = &rest[backslash + 1 + length..];
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// Read the escape sequence following a backslash.
+/// Returns the escaped character and the length of the sequence in bytes.
+fn read_escape(text: &str) -> Result<(char, usize), &'static str> {
+    let escaped = match nth_char(text, 0) {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        '0' => '\0',
+        '\\' => '\\',
+        '"' => '"',
+        '$' => '$',
+        'u' => return read_unicode_escape(text),
+        _ => return Err("Invalid escape sequence."),
+    };
+    Ok((escaped, 1))
+}
+
+/// Read a unicode escape sequence like `u{1F600}`
+fn read_unicode_escape(text: &str) -> Result<(char, usize), &'static str> {
+    let message = "Invalid unicode escape sequence.";
+    if nth_char(text, 1) != '{' {
+        return Err(message);
+    }
+    let closing_brace = text.find('}').ok_or(message)?;
+    let hex_digits = &text[2..closing_brace];
+    if hex_digits.is_empty() || hex_digits.len() > 6 {
+        return Err(message);
+    }
+    let code_point = u32::from_str_radix(hex_digits, 16).map_err(|_| message)?;
+    let escaped = char::from_u32(code_point).ok_or(message)?;
+    Ok((escaped, closing_brace + 1))
+}
+
 fn make_token(source: &Source, token_type: TokenType) -> Token {
     Token::new(
         token_type,
@@ -225,28 +459,29 @@ fn error_token(line: usize, message: &str) -> Token {
 }
 
 fn advance(source: &mut Source) -> char {
-    let ret = nth_char(source.text.clone(), source.current);
-    source.current += 1;
+    let ret = nth_char(&source.text, source.current);
+    source.current += ret.len_utf8();
     ret
 }
 
 fn is_at_end(source: &Source) -> bool {
-    nth_char(source.text.clone(), source.current) == '\0'
+    nth_char(&source.text, source.current) == '\0'
 }
 
-fn nth_char(text: String, n: usize) -> char {
-    if text.len() == n {
-        return '\0';
-    }
-    text.chars().nth(n).unwrap()
+/// Returns the character starting at the n-th byte
+/// The character at byte offset n, or '\0' past the end or inside a multibyte character
+fn nth_char(text: &str, n: usize) -> char {
+    text.get(n..)
+        .and_then(|rest| rest.chars().next())
+        .unwrap_or('\0')
 }
 
 fn is_digit(c: char) -> bool {
-    c >= '0' && c <= '9'
+    c.is_ascii_digit()
 }
 
 fn is_alpha(c: char) -> bool {
-    (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || c == '_'
+    c.is_ascii_alphabetic() || c == '_'
 }
 
 #[cfg(test)]
@@ -256,6 +491,18 @@ mod tests {
     mod helper {
         use super::*;
 
+        #[test]
+        fn test_restore() {
+            let mut source = Source::new("a\nb c".to_string());
+            scan_token(&mut source);
+            let position = source.position();
+            assert_eq!(scan_token(&mut source).lexeme, "b");
+            assert_eq!(source.line, 2);
+            source.restore(position);
+            assert_eq!(source.line, 1);
+            assert_eq!(scan_token(&mut source).lexeme, "b");
+        }
+
         #[test]
         fn test_is_digit() {
             assert!(is_digit('8'));
@@ -274,6 +521,15 @@ mod tests {
             assert_eq!(result.lexeme, "(");
         }
 
+        #[test]
+        fn test_brackets() {
+            let mut source = Source::new("[]".to_string());
+            let result = scan_token(&mut source);
+            assert_eq!(result.token_type, TokenType::LeftBracket);
+            let result = scan_token(&mut source);
+            assert_eq!(result.token_type, TokenType::RightBracket);
+        }
+
         #[test]
         fn test_pair_of_token() {
             let mut source = Source::new("!=".to_string());
@@ -282,6 +538,60 @@ mod tests {
             assert_eq!(result.lexeme, "!=");
         }
 
+        #[test]
+        fn test_operators() {
+            let mut source = Source::new("** * << >> % & | ^ ~".to_string());
+            let expected = [
+                TokenType::StarStar,
+                TokenType::Star,
+                TokenType::LessLess,
+                TokenType::GreaterGreater,
+                TokenType::Percent,
+                TokenType::Ampersand,
+                TokenType::Pipe,
+                TokenType::Caret,
+                TokenType::Tilde,
+            ];
+            for token_type in expected {
+                assert_eq!(scan_token(&mut source).token_type, token_type);
+            }
+        }
+
+        #[test]
+        fn test_nil_safe_operators() {
+            let mut source = Source::new("?? ?.( ?".to_string());
+            let expected = [
+                TokenType::QuestionQuestion,
+                TokenType::QuestionDot,
+                TokenType::LeftParen,
+                TokenType::Question,
+            ];
+            for token_type in expected {
+                assert_eq!(scan_token(&mut source).token_type, token_type);
+            }
+        }
+
+        #[test]
+        fn test_assignment_operators() {
+            let mut source = Source::new("+= -= *= /= ++ -- - - => ... .".to_string());
+            let expected = [
+                TokenType::PlusEqual,
+                TokenType::MinusEqual,
+                TokenType::StarEqual,
+                TokenType::SlashEqual,
+                TokenType::PlusPlus,
+                TokenType::MinusMinus,
+                TokenType::Minus,
+                TokenType::Minus,
+                TokenType::Arrow,
+                TokenType::DotDotDot,
+                TokenType::Dot,
+            ];
+            for token_type in expected {
+                assert_eq!(scan_token(&mut source).token_type, token_type);
+            }
+        }
+
         #[test]
         fn test_error_token() {
             let mut source = Source::new("エラー".to_string());
@@ -314,6 +624,51 @@ mod tests {
             assert_eq!(token.lexeme, "\"abcd\nefg\"");
         }
 
+        #[test]
+        fn test_multibyte_string() {
+            let mut source = Source::new("\"héllo\"".to_string());
+            let token = scan_token(&mut source);
+            assert_eq!(token.token_type, TokenType::LString);
+            assert_eq!(token.lexeme, "\"héllo\"");
+        }
+
+        #[test]
+        fn test_escape_sequence() {
+            let mut source = Source::new(r#""a\"b\n\u{3042}""#.to_string());
+            let token = scan_token(&mut source);
+            assert_eq!(token.token_type, TokenType::LString);
+            assert_eq!(string_content(&token), "a\"b\nあ");
+        }
+
+        #[test]
+        fn test_invalid_escape_sequence() {
+            let mut source = Source::new(r#""\q""#.to_string());
+            let token = scan_token(&mut source);
+            assert_eq!(token.token_type, TokenType::Error);
+            assert_eq!(token.lexeme, "Invalid escape sequence.");
+
+            let mut source = Source::new(r#""\u{110000}""#.to_string());
+            let token = scan_token(&mut source);
+            assert_eq!(token.token_type, TokenType::Error);
+            assert_eq!(token.lexeme, "Invalid unicode escape sequence.");
+        }
+
+        #[test]
+        fn test_interpolation() {
+            let mut source = Source::new(r#""a${b}c""#.to_string());
+            let token = scan_token(&mut source);
+            assert_eq!(token.token_type, TokenType::Interpolation);
+            assert_eq!(string_content(&token), "a");
+            let token = scan_token(&mut source);
+            assert_eq!(token.lexeme, "b");
+            let token = scan_token(&mut source);
+            assert_eq!(token.token_type, TokenType::RightBrace);
+            let token = continue_string(&mut source);
+            assert_eq!(token.token_type, TokenType::LString);
+            assert_eq!(token.lexeme, "}c\"");
+            assert_eq!(string_content(&token), "c");
+        }
+
         #[test]
         fn test_unterminated_string() {
             let mut source = Source {
@@ -420,8 +775,11 @@ mod tests {
 
     #[test]
     fn test_nth_char() {
-        assert_eq!(nth_char("abcde".to_string(), 3), 'd');
-        assert_eq!(nth_char("".to_string(), 0), '\0');
+        assert_eq!(nth_char("abcde", 3), 'd');
+        assert_eq!(nth_char("", 0), '\0');
+        assert_eq!(nth_char("aéb", 3), 'b');
+        assert_eq!(nth_char("ab", 3), '\0');
+        assert_eq!(nth_char("aéb", 2), '\0');
     }
 
     #[test]
@@ -455,14 +813,14 @@ mod tests {
         #[test]
         fn test_white_space() {
             let mut source = Source::new(" \r\t".to_string());
-            skip_white_space(&mut source);
+            skip_white_space(&mut source).unwrap();
             assert_eq!(source.current, 3);
         }
 
         #[test]
         fn test_new_line() {
             let mut source = Source::new("\n\n".to_string());
-            skip_white_space(&mut source);
+            skip_white_space(&mut source).unwrap();
             assert_eq!(source.current, 2);
             assert_eq!(source.line, 3);
         }
@@ -470,10 +828,27 @@ mod tests {
         #[test]
         fn test_comment() {
             let mut source = Source::new("// comment".to_string());
-            skip_white_space(&mut source);
+            skip_white_space(&mut source).unwrap();
             assert_eq!(source.current, 10);
             assert_eq!(source.line, 1);
         }
+
+        #[test]
+        fn test_block_comment() {
+            let mut source = Source::new("/* a /* b\n */ c\n */ d".to_string());
+            skip_white_space(&mut source).unwrap();
+            assert_eq!(source.current, 20);
+            assert_eq!(source.line, 3);
+        }
+
+        #[test]
+        fn test_unterminated_block_comment() {
+            let mut source = Source::new("\n/* a /* b */\n".to_string());
+            let token = scan_token(&mut source);
+            assert_eq!(token.token_type, TokenType::Error);
+            assert_eq!(token.lexeme, "Unterminated block comment.");
+            assert_eq!(token.line, 2);
+        }
     }
 
     #[test]
@@ -504,6 +879,168 @@ mod tests {
             assert_eq!(identifier_type(&source), TokenType::And);
         }
 
+        #[test]
+        fn test_assert() {
+            let source = Source {
+                text: "assert".to_string(),
+                start: 0,
+                current: 6,
+                line: 1,
+            };
+            assert_eq!(identifier_type(&source), TokenType::Assert);
+        }
+
+        #[test]
+        fn test_break() {
+            let source = Source {
+                text: "break".to_string(),
+                start: 0,
+                current: 5,
+                line: 1,
+            };
+            assert_eq!(identifier_type(&source), TokenType::Break);
+        }
+
+        #[test]
+        fn test_continue() {
+            let source = Source {
+                text: "continue".to_string(),
+                start: 0,
+                current: 8,
+                line: 1,
+            };
+            assert_eq!(identifier_type(&source), TokenType::Continue);
+        }
+
+        #[test]
+        fn test_throw() {
+            let source = Source {
+                text: "throw".to_string(),
+                start: 0,
+                current: 5,
+                line: 1,
+            };
+            assert_eq!(identifier_type(&source), TokenType::Throw);
+        }
+
+        #[test]
+        fn test_try() {
+            let source = Source {
+                text: "try".to_string(),
+                start: 0,
+                current: 3,
+                line: 1,
+            };
+            assert_eq!(identifier_type(&source), TokenType::Try);
+        }
+
+        #[test]
+        fn test_catch() {
+            let source = Source {
+                text: "catch".to_string(),
+                start: 0,
+                current: 5,
+                line: 1,
+            };
+            assert_eq!(identifier_type(&source), TokenType::Catch);
+        }
+
+        #[test]
+        fn test_match() {
+            let source = Source {
+                text: "match".to_string(),
+                start: 0,
+                current: 5,
+                line: 1,
+            };
+            assert_eq!(identifier_type(&source), TokenType::Match);
+        }
+
+        #[test]
+        fn test_case() {
+            let source = Source {
+                text: "case".to_string(),
+                start: 0,
+                current: 4,
+                line: 1,
+            };
+            assert_eq!(identifier_type(&source), TokenType::Case);
+        }
+
+        #[test]
+        fn test_default() {
+            let source = Source {
+                text: "default".to_string(),
+                start: 0,
+                current: 7,
+                line: 1,
+            };
+            assert_eq!(identifier_type(&source), TokenType::Default);
+        }
+
+        #[test]
+        fn test_finally() {
+            let source = Source {
+                text: "finally".to_string(),
+                start: 0,
+                current: 7,
+                line: 1,
+            };
+            assert_eq!(identifier_type(&source), TokenType::Finally);
+        }
+
+        #[test]
+        fn test_keyword_prefix() {
+            let source = Source {
+                text: "tr".to_string(),
+                start: 0,
+                current: 2,
+                line: 1,
+            };
+            assert_eq!(identifier_type(&source), TokenType::Identifier);
+        }
+
+        #[test]
+        fn test_import() {
+            let source = Source {
+                text: "import".to_string(),
+                start: 0,
+                current: 6,
+                line: 1,
+            };
+            assert_eq!(identifier_type(&source), TokenType::Import);
+        }
+
+        #[test]
+        fn test_co_at_end() {
+            let mut source = Source::new("co".to_string());
+            let token = scan_token(&mut source);
+            assert_eq!(token.token_type, TokenType::Identifier);
+            assert_eq!(token.lexeme, "co");
+        }
+
+        #[test]
+        fn test_co_before_multibyte_character() {
+            let source = Source {
+                text: "coé".to_string(),
+                start: 0,
+                current: 2,
+                line: 1,
+            };
+            assert_eq!(identifier_type(&source), TokenType::Identifier);
+        }
+
+        #[test]
+        fn test_const() {
+            let source = Source {
+                text: "const".to_string(),
+                start: 0,
+                current: 5,
+                line: 1,
+            };
+            assert_eq!(identifier_type(&source), TokenType::Const);
+        }
+
         #[test]
         fn test_class() {
             let source = Source {
@@ -15,6 +15,7 @@ impl Token {
     }
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum TokenType {
     // Single-character tokens.
@@ -22,13 +23,24 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
+    Colon,
+    Question,
+    Arrow,
+    DotDotDot,
     Dot,
     Minus,
     Plus,
     Semicolon,
     Slash,
     Star,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
 
     // One or two character tokens.
     Bang,
@@ -39,15 +51,41 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    LessLess,
+    GreaterGreater,
+    StarStar,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    PlusPlus,
+    MinusMinus,
+    QuestionQuestion,
+    QuestionDot,
 
     // Literals.
     Identifier,
     LString,
+    /// Part of a string literal followed by an interpolated expression, like `"a ${`
+    Interpolation,
     Number,
 
     // Keywords.
     And,
+    Assert,
+    Break,
     Class,
+    Continue,
+    Throw,
+    Try,
+    Catch,
+    Finally,
+    Import,
+    Const,
+    Yield,
+    Match,
+    Case,
+    Default,
     Else,
     False,
     Fun,
@@ -1,4 +1,4 @@
-use std::fmt::Debug;
+use std::{cmp::Ordering, fmt, rc::Rc};
 
 use self::object::Obj;
 
@@ -8,6 +8,7 @@ pub mod object;
 pub enum Value {
     Bool(bool),
     Nil,
+    Integer(i64),
     Number(f64),
     LString(String),
     Obj(Obj),
@@ -16,11 +17,24 @@ pub enum Value {
 impl Value {
     pub fn as_number(&self) -> f64 {
         match self {
+            Value::Integer(integer) => *integer as f64,
             Value::Number(number) => *number,
             _ => panic!("Not number"),
         }
     }
 
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(integer) => Some(*integer),
+            Value::Number(number)
+                if number.fract() == 0.0 && (-(2f64.powi(63))..2f64.powi(63)).contains(number) =>
+            {
+                Some(*number as i64)
+            }
+            _ => None,
+        }
+    }
+
     pub fn as_string(&self) -> String {
         match self {
             Value::LString(string) => string.to_string(),
@@ -30,25 +44,84 @@ impl Value {
 
     pub fn values_equal(&self, b: Self) -> bool {
         match (self, b) {
-            (Value::Bool(boolean1), Value::Bool(boolean2)) => boolean1.to_owned() == boolean2,
+            (Value::Bool(boolean1), Value::Bool(boolean2)) => *boolean1 == boolean2,
             (Value::Nil, Value::Nil) => true,
-            (Value::Number(num1), Value::Number(num2)) => num1.to_owned() == num2,
-            (Value::LString(str1), Value::LString(str2)) => str1.to_owned() == str2,
+            (Value::Integer(int1), Value::Integer(int2)) => *int1 == int2,
+            (Value::Number(num1), Value::Number(num2)) => *num1 == num2,
+            (Value::Integer(int), Value::Number(num)) => {
+                compare_integer_to_number(*int, num) == Some(Ordering::Equal)
+            }
+            (Value::Number(num), Value::Integer(int)) => {
+                compare_integer_to_number(int, *num) == Some(Ordering::Equal)
+            }
+            (Value::LString(str1), Value::LString(str2)) => *str1 == str2,
+            (Value::Obj(Obj::Function(fun1)), Value::Obj(Obj::Function(fun2))) => {
+                Rc::ptr_eq(fun1, &fun2)
+            }
+            (Value::Obj(Obj::Closure(closure1)), Value::Obj(Obj::Closure(closure2))) => {
+                Rc::ptr_eq(closure1, &closure2)
+            }
+            (Value::Obj(Obj::Class(class1)), Value::Obj(Obj::Class(class2))) => {
+                Rc::ptr_eq(class1, &class2)
+            }
+            (Value::Obj(Obj::Instance(instance1)), Value::Obj(Obj::Instance(instance2))) => {
+                Rc::ptr_eq(instance1, &instance2)
+            }
+            (Value::Obj(Obj::BoundMethod(method1)), Value::Obj(Obj::BoundMethod(method2))) => {
+                Rc::ptr_eq(method1, &method2)
+            }
+            (Value::Obj(Obj::List(list1)), Value::Obj(Obj::List(list2))) => {
+                Rc::ptr_eq(list1, &list2)
+            }
+            (Value::Obj(Obj::Map(map1)), Value::Obj(Obj::Map(map2))) => Rc::ptr_eq(map1, &map2),
+            (Value::Obj(Obj::Error(error1)), Value::Obj(Obj::Error(error2))) => {
+                Rc::ptr_eq(error1, &error2)
+            }
+            (Value::Obj(Obj::Module(module1)), Value::Obj(Obj::Module(module2))) => {
+                Rc::ptr_eq(module1, &module2)
+            }
+            (Value::Obj(Obj::Coroutine(coroutine1)), Value::Obj(Obj::Coroutine(coroutine2))) => {
+                Rc::ptr_eq(coroutine1, &coroutine2)
+            }
+            // tuples are immutable, so they are equal when their items are
+            (Value::Obj(Obj::Tuple(tuple1)), Value::Obj(Obj::Tuple(tuple2))) => {
+                tuple1.items.len() == tuple2.items.len()
+                    && tuple1
+                        .items
+                        .iter()
+                        .zip(&tuple2.items)
+                        .all(|(item1, item2)| item1.values_equal(item2.clone()))
+            }
             (_, _) => false,
         }
     }
 
     pub fn println(&self) {
-        println!("{}", self.to_string());
+        println!("{self}");
+    }
+}
+
+/// Compare an integer with a float exactly, which is None if the float is NaN.
+/// Casting the integer rounds it to the nearest float,
+/// which only decides the order when it isn't equal to the number.
+pub fn compare_integer_to_number(int: i64, num: f64) -> Option<Ordering> {
+    match (int as f64).partial_cmp(&num)? {
+        // 2^63 is the only float the cast of an integer rounds up to which no integer equals
+        Ordering::Equal if num >= i64::MAX as f64 => Some(Ordering::Less),
+        Ordering::Equal => Some(int.cmp(&(num as i64))),
+        ordering => Some(ordering),
     }
+}
 
-    fn to_string(&self) -> String {
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Self::Bool(boolean) => boolean.to_string(),
-            Self::Nil => "nil".to_string(),
-            Self::Number(num) => num.to_string(),
-            Self::LString(string) => string.to_string(),
-            Self::Obj(obj) => obj.to_string(),
+            Self::Bool(boolean) => write!(f, "{boolean}"),
+            Self::Nil => write!(f, "nil"),
+            Self::Integer(integer) => write!(f, "{integer}"),
+            Self::Number(num) => write!(f, "{num}"),
+            Self::LString(string) => write!(f, "{string}"),
+            Self::Obj(obj) => write!(f, "{obj}"),
         }
     }
 }
@@ -67,6 +140,8 @@ mod tests {
         assert_eq!(num.to_string(), "1");
         let num = Value::Number(1.5);
         assert_eq!(num.to_string(), "1.5");
+        let integer = Value::Integer(9007199254740993);
+        assert_eq!(integer.to_string(), "9007199254740993");
         let string = Value::LString("ABC".to_string());
         assert_eq!(string.to_string(), "ABC");
     }
@@ -96,6 +171,28 @@ mod tests {
         assert!(!num1.values_equal(num3));
     }
 
+    #[test]
+    fn test_values_equal_integer() {
+        let int1 = Value::Integer(2);
+        assert!(int1.values_equal(Value::Integer(2)));
+        assert!(int1.values_equal(Value::Number(2.0)));
+        assert!(!int1.values_equal(Value::Number(2.5)));
+        assert!(Value::Integer(0).values_equal(Value::Number(-0.0)));
+        let big = Value::Integer(9007199254740993);
+        assert!(!big.values_equal(Value::Number(9007199254740992.0)));
+        assert!(!Value::Number(9223372036854775808.0).values_equal(Value::Integer(i64::MAX)));
+        assert!(!Value::Integer(1).values_equal(Value::Number(f64::NAN)));
+    }
+
+    #[test]
+    fn test_as_integer() {
+        assert_eq!(Value::Integer(-4).as_integer(), Some(-4));
+        assert_eq!(Value::Number(3.0).as_integer(), Some(3));
+        assert_eq!(Value::Number(3.5).as_integer(), None);
+        assert_eq!(Value::Number(f64::INFINITY).as_integer(), None);
+        assert_eq!(Value::Nil.as_integer(), None);
+    }
+
     #[test]
     fn test_values_equal_string() {
         let str1 = Value::LString("AAA".to_string());
@@ -1,40 +1,168 @@
-use crate::chunk::Chunk;
+use std::{
+    cell::{Cell, RefCell},
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fmt,
+    hash::{Hash, Hasher},
+    ops::RangeInclusive,
+    path::PathBuf,
+    rc::Rc,
+};
 
-use super::Value;
+use crate::{chunk::Chunk, vm::ExecutionContext};
+
+use super::{compare_integer_to_number, Value};
 
 #[derive(Debug, Clone)]
 pub enum Obj {
-    Function(ObjFunction),
+    Function(Rc<ObjFunction>),
     NativeFunction(ObjNative),
+    Closure(Rc<ObjClosure>),
+    Class(Rc<RefCell<ObjClass>>),
+    Instance(Rc<RefCell<ObjInstance>>),
+    BoundMethod(Rc<ObjBoundMethod>),
+    BoundNative(Rc<ObjBoundNative>),
+    List(Rc<RefCell<ObjList>>),
+    Map(Rc<RefCell<ObjMap>>),
+    Error(Rc<RefCell<ObjError>>),
+    Module(Rc<ObjModule>),
+    Coroutine(Rc<RefCell<ObjCoroutine>>),
+    /// The `resume` method of a coroutine, which is also available as `next`
+    Resume(Rc<RefCell<ObjCoroutine>>),
+    Range(ObjRange),
+    Tuple(Rc<ObjTuple>),
+    Iterator(Rc<RefCell<ObjIterator>>),
 }
 
-impl Obj {
-    pub fn to_string(&self) -> String {
+impl fmt::Display for Obj {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Self::Function(function) => format!("<fn {}>", function.name),
-            Self::NativeFunction(_) => "<native fn>".to_string(),
+            Self::Function(function) => write!(f, "{function}"),
+            Self::NativeFunction(_) => write!(f, "<native fn>"),
+            Self::Closure(closure) => write!(f, "{}", closure.function),
+            Self::Class(class) => write!(f, "{}", class.borrow().name),
+            Self::Instance(instance) => {
+                write!(f, "{} instance", instance.borrow().class.borrow().name)
+            }
+            Self::BoundMethod(bound_method) => write!(f, "{}", bound_method.method.function),
+            Self::BoundNative(_) => write!(f, "<native fn>"),
+            Self::List(list) => write_once(f, list, "[...]", |f| {
+                let items: Vec<String> = list.borrow().items.iter().map(format_item).collect();
+                write!(f, "[{}]", items.join(", "))
+            }),
+            Self::Map(map) => write_once(f, map, "{...}", |f| {
+                let entries: Vec<String> = map
+                    .borrow()
+                    .sorted_entries()
+                    .iter()
+                    .map(|(key, value)| {
+                        format!("{}: {}", format_item(&key.to_value()), format_item(value))
+                    })
+                    .collect();
+                write!(f, "{{{}}}", entries.join(", "))
+            }),
+            Self::Error(error) => write!(f, "Error: {}", error.borrow().message),
+            Self::Module(module) => write!(f, "{module}"),
+            Self::Coroutine(coroutine) => write!(f, "{}", coroutine.borrow()),
+            Self::Resume(_) => write!(f, "<native fn>"),
+            Self::Range(range) => write!(f, "{range}"),
+            Self::Tuple(tuple) => {
+                let items: Vec<String> = tuple.items.iter().map(format_item).collect();
+                write!(f, "({})", items.join(", "))
+            }
+            Self::Iterator(_) => write!(f, "<iterator>"),
         }
     }
 }
 
+thread_local! {
+    /// Lists and maps whose items are being formatted, outermost first
+    static FORMATTING: RefCell<Vec<*const ()>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Format the items of a list or a map with `write_items`,
+/// or write `placeholder` instead if it's one of its own items
+fn write_once<T>(
+    f: &mut fmt::Formatter,
+    collection: &Rc<T>,
+    placeholder: &str,
+    write_items: impl FnOnce(&mut fmt::Formatter) -> fmt::Result,
+) -> fmt::Result {
+    let pointer = Rc::as_ptr(collection) as *const ();
+    if FORMATTING.with_borrow(|formatting| formatting.contains(&pointer)) {
+        return write!(f, "{placeholder}");
+    }
+    FORMATTING.with_borrow_mut(|formatting| formatting.push(pointer));
+    let result = write_items(f);
+    FORMATTING.with_borrow_mut(|formatting| formatting.pop());
+    result
+}
+
+/// Items of lists, maps and tuples, where strings are quoted to tell `"1"` from `1`
+fn format_item(item: &Value) -> String {
+    match item {
+        Value::LString(string) => format!("\"{string}\""),
+        _ => item.to_string(),
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ObjNative {
     pub native_function: NativeFunction,
+    pub min_arity: usize,
+    pub max_arity: usize,
 }
 
-pub type NativeFunction = fn(arg_count: usize, ip: usize) -> Value;
+/// Receives the arguments and returns the result or an error message
+pub type NativeFunction = fn(args: &[Value]) -> Result<Value, String>;
 
 impl ObjNative {
-    pub fn new(native_function: NativeFunction) -> ObjNative {
-        ObjNative { native_function }
+    pub fn new(native_function: NativeFunction, arity: RangeInclusive<usize>) -> ObjNative {
+        ObjNative {
+            native_function,
+            min_arity: *arity.start(),
+            max_arity: *arity.end(),
+        }
+    }
+
+    pub fn accepts(&self, arg_count: usize) -> bool {
+        (self.min_arity..=self.max_arity).contains(&arg_count)
+    }
+
+    /// The accepted number of arguments for error messages
+    pub fn arity_range(&self) -> String {
+        if self.min_arity == self.max_arity {
+            self.min_arity.to_string()
+        } else {
+            format!("{} to {}", self.min_arity, self.max_arity)
+        }
     }
 }
 
+/// Describes where a closure captures a variable from when it's created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpvalueIndex {
+    /// Stack slot of the enclosing function's local when `is_local` is true,
+    /// otherwise index of the enclosing function's upvalue.
+    pub index: usize,
+    pub is_local: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct ObjFunction {
     pub name: String,
     pub chunk: Chunk,
-    pub arity: usize,
+    /// Number of parameters without a default value
+    pub min_arity: usize,
+    /// Number of parameters, not counting the rest parameter
+    pub max_arity: usize,
+    /// Whether the last parameter collects the extra arguments into a list
+    pub variadic: bool,
+    /// Whether the function contains `yield`, so that calling it creates a coroutine
+    pub generator: bool,
+    /// Whether the function is the top-level code of the main script or a module
+    pub script: bool,
+    pub upvalues: Vec<UpvalueIndex>,
 }
 
 impl ObjFunction {
@@ -42,7 +170,563 @@ impl ObjFunction {
         ObjFunction {
             name: String::new(),
             chunk: Chunk::new(),
-            arity: 0,
+            min_arity: 0,
+            max_arity: 0,
+            variadic: false,
+            generator: false,
+            script: false,
+            upvalues: Vec::new(),
+        }
+    }
+
+    pub fn accepts(&self, arg_count: usize) -> bool {
+        arg_count >= self.min_arity && (self.variadic || arg_count <= self.max_arity)
+    }
+
+    /// The accepted number of arguments for error messages
+    pub fn arity_range(&self) -> String {
+        if self.variadic {
+            format!("at least {}", self.min_arity)
+        } else if self.min_arity == self.max_arity {
+            self.min_arity.to_string()
+        } else {
+            format!("{} to {}", self.min_arity, self.max_arity)
+        }
+    }
+}
+
+impl fmt::Display for ObjFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.script {
+            write!(f, "<script>")
+        } else {
+            write!(f, "<fn {}>", self.name)
+        }
+    }
+}
+
+/// A variable captured by a closure.
+/// While the variable is still alive on the stack, it points to the stack slot.
+/// Once the variable goes out of scope, the value is moved into the upvalue itself.
+#[derive(Debug, Clone)]
+pub enum ObjUpvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+#[derive(Debug)]
+pub struct ObjClosure {
+    pub function: Rc<ObjFunction>,
+    pub upvalues: Vec<Rc<RefCell<ObjUpvalue>>>,
+    /// Module whose globals the function uses
+    pub module: Rc<ObjModule>,
+}
+
+impl ObjClosure {
+    pub fn new(
+        function: Rc<ObjFunction>,
+        upvalues: Vec<Rc<RefCell<ObjUpvalue>>>,
+        module: Rc<ObjModule>,
+    ) -> ObjClosure {
+        ObjClosure {
+            function,
+            upvalues,
+            module,
+        }
+    }
+}
+
+/// A script file with its own global variables, which are the exports of the module
+#[derive(Debug)]
+pub struct ObjModule {
+    /// Canonical path of the file, empty when the script isn't read from a file
+    pub path: PathBuf,
+    pub globals: RefCell<HashMap<String, Value>>,
+    /// Names of the globals declared with `const`
+    pub constants: RefCell<HashSet<String>>,
+    /// Whether the script of the module ran to the end
+    pub loaded: Cell<bool>,
+}
+
+impl ObjModule {
+    pub fn new(path: PathBuf) -> ObjModule {
+        ObjModule {
+            path,
+            globals: RefCell::new(HashMap::new()),
+            constants: RefCell::new(HashSet::new()),
+            loaded: Cell::new(false),
+        }
+    }
+
+    /// File name without the extension
+    pub fn name(&self) -> String {
+        match self.path.file_stem() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => String::new(),
+        }
+    }
+}
+
+impl fmt::Display for ObjModule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<module {}>", self.name())
+    }
+}
+
+/// A call of a generator function, which runs until the next `yield` each time it's resumed
+#[derive(Debug)]
+pub struct ObjCoroutine {
+    pub name: String,
+    pub state: CoroutineState,
+    /// Whether the coroutine has been resumed before,
+    /// so that there is a `yield` waiting for the resumed value
+    pub started: bool,
+}
+
+#[derive(Debug)]
+pub enum CoroutineState {
+    /// The stack and the frames of the coroutine while it's not running
+    Suspended(ExecutionContext),
+    Running,
+    Done,
+}
+
+impl ObjCoroutine {
+    pub fn new(name: String, context: ExecutionContext) -> ObjCoroutine {
+        ObjCoroutine {
+            name,
+            state: CoroutineState::Suspended(context),
+            started: false,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, CoroutineState::Done)
+    }
+}
+
+impl fmt::Display for ObjCoroutine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<coroutine {}>", self.name)
+    }
+}
+
+#[derive(Debug)]
+pub struct ObjClass {
+    pub name: String,
+    pub methods: HashMap<String, Rc<ObjClosure>>,
+}
+
+impl ObjClass {
+    pub fn new(name: String) -> ObjClass {
+        ObjClass {
+            name,
+            methods: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ObjInstance {
+    pub class: Rc<RefCell<ObjClass>>,
+    pub fields: HashMap<String, Value>,
+}
+
+impl ObjInstance {
+    pub fn new(class: Rc<RefCell<ObjClass>>) -> ObjInstance {
+        ObjInstance {
+            class,
+            fields: HashMap::new(),
+        }
+    }
+}
+
+/// A method bound to the instance it was accessed from,
+/// so that `this` still refers to the instance when it's called later.
+#[derive(Debug)]
+pub struct ObjBoundMethod {
+    pub receiver: Value,
+    pub method: Rc<ObjClosure>,
+}
+
+impl ObjBoundMethod {
+    pub fn new(receiver: Value, method: Rc<ObjClosure>) -> ObjBoundMethod {
+        ObjBoundMethod { receiver, method }
+    }
+}
+
+/// A built-in method like `"abc".upper` bound to the value it was accessed from.
+/// The receiver is passed to the native function as its first argument.
+#[derive(Debug)]
+pub struct ObjBoundNative {
+    pub receiver: Value,
+    pub native: ObjNative,
+}
+
+impl ObjBoundNative {
+    pub fn new(receiver: Value, native: ObjNative) -> ObjBoundNative {
+        ObjBoundNative { receiver, native }
+    }
+}
+
+#[derive(Debug)]
+pub struct ObjList {
+    pub items: Vec<Value>,
+}
+
+impl ObjList {
+    pub fn new(items: Vec<Value>) -> ObjList {
+        ObjList { items }
+    }
+}
+
+/// Immutable values returned together like `return a, b;`
+#[derive(Debug)]
+pub struct ObjTuple {
+    pub items: Vec<Value>,
+}
+
+impl ObjTuple {
+    pub fn new(items: Vec<Value>) -> ObjTuple {
+        ObjTuple { items }
+    }
+}
+
+/// Integers from `start` up to but not including `end`, created by `range()`
+#[derive(Debug, Clone, Copy)]
+pub struct ObjRange {
+    pub start: i64,
+    pub end: i64,
+    pub step: i64,
+}
+
+impl fmt::Display for ObjRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "range({}, {}, {})", self.start, self.end, self.step)
+    }
+}
+
+/// Position of a `for ... in` loop over a built-in value
+#[derive(Debug)]
+pub enum ObjIterator {
+    /// Items of a list, which may change during the loop
+    List {
+        list: Rc<RefCell<ObjList>>,
+        index: usize,
+    },
+    /// Characters of a string from the byte offset
+    Chars {
+        string: String,
+        offset: usize,
+    },
+    Range {
+        next: i64,
+        end: i64,
+        step: i64,
+    },
+    /// Keys of a map, which are sorted when the loop starts
+    Keys(std::vec::IntoIter<Value>),
+    Done,
+}
+
+impl ObjIterator {
+    pub fn is_done(&self) -> bool {
+        matches!(self, ObjIterator::Done)
+    }
+}
+
+impl Iterator for ObjIterator {
+    type Item = Value;
+
+    /// The iterator becomes `Done` once there are no more values
+    fn next(&mut self) -> Option<Value> {
+        let value = match self {
+            ObjIterator::List { list, index } => {
+                let item = list.borrow().items.get(*index).cloned();
+                *index += 1;
+                item
+            }
+            ObjIterator::Chars { string, offset } => string[*offset..].chars().next().map(|c| {
+                *offset += c.len_utf8();
+                Value::LString(c.to_string())
+            }),
+            ObjIterator::Range { next, end, step } => {
+                let in_range = if *step > 0 { next < end } else { next > end };
+                in_range.then(|| {
+                    let value = *next;
+                    // stop instead of overflowing at the end of the integers
+                    *next = next.checked_add(*step).unwrap_or(*end);
+                    Value::Integer(value)
+                })
+            }
+            ObjIterator::Keys(keys) => keys.next(),
+            ObjIterator::Done => None,
+        };
+        if value.is_none() {
+            *self = ObjIterator::Done;
+        }
+        value
+    }
+}
+
+/// Values which can be used as keys of maps
+#[derive(Debug, Clone)]
+pub enum MapKey {
+    Nil,
+    Bool(bool),
+    Integer(i64),
+    Number(f64),
+    LString(String),
+}
+
+impl MapKey {
+    /// Returns None if the value can't be hashed
+    pub fn from_value(value: &Value) -> Option<MapKey> {
+        match value {
+            Value::Nil => Some(MapKey::Nil),
+            Value::Bool(boolean) => Some(MapKey::Bool(*boolean)),
+            // 1 and 1.0 (as well as -0 and 0) should be the same key
+            Value::Integer(_) | Value::Number(_) => Some(
+                value
+                    .as_integer()
+                    .map_or(MapKey::Number(value.as_number()), MapKey::Integer),
+            ),
+            Value::LString(string) => Some(MapKey::LString(string.clone())),
+            Value::Obj(_) => None,
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            MapKey::Nil => Value::Nil,
+            MapKey::Bool(boolean) => Value::Bool(*boolean),
+            MapKey::Integer(integer) => Value::Integer(*integer),
+            MapKey::Number(number) => Value::Number(*number),
+            MapKey::LString(string) => Value::LString(string.clone()),
+        }
+    }
+
+    /// Order of the variants when sorting keys of different types
+    fn rank(&self) -> u8 {
+        match self {
+            MapKey::Nil => 0,
+            MapKey::Bool(_) => 1,
+            MapKey::Integer(_) | MapKey::Number(_) => 2,
+            MapKey::LString(_) => 3,
+        }
+    }
+}
+
+impl PartialEq for MapKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for MapKey {}
+
+impl Hash for MapKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.rank().hash(state);
+        match self {
+            MapKey::Nil => (),
+            MapKey::Bool(boolean) => boolean.hash(state),
+            MapKey::Integer(integer) => integer.hash(state),
+            MapKey::Number(number) => number.to_bits().hash(state),
+            MapKey::LString(string) => string.hash(state),
+        }
+    }
+}
+
+impl PartialOrd for MapKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for MapKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (MapKey::Bool(bool1), MapKey::Bool(bool2)) => bool1.cmp(bool2),
+            (MapKey::Integer(int1), MapKey::Integer(int2)) => int1.cmp(int2),
+            (MapKey::Number(num1), MapKey::Number(num2)) => num1.total_cmp(num2),
+            (MapKey::Integer(int), MapKey::Number(num)) => compare_key_numbers(*int, *num),
+            (MapKey::Number(num), MapKey::Integer(int)) => {
+                compare_key_numbers(*int, *num).reverse()
+            }
+            (MapKey::LString(str1), MapKey::LString(str2)) => str1.cmp(str2),
+            (_, _) => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+/// Compare exactly, so that keys are only equal when they hash the same.
+/// NaN, which is never equal to an integer, is ordered the way `total_cmp` orders it.
+fn compare_key_numbers(int: i64, num: f64) -> Ordering {
+    compare_integer_to_number(int, num).unwrap_or_else(|| (int as f64).total_cmp(&num))
+}
+
+#[derive(Debug)]
+pub struct ObjMap {
+    pub entries: HashMap<MapKey, Value>,
+}
+
+impl ObjMap {
+    pub fn new() -> ObjMap {
+        ObjMap {
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Entries ordered by key so that the output doesn't depend on hashing
+    pub fn sorted_entries(&self) -> Vec<(&MapKey, &Value)> {
+        let mut entries: Vec<(&MapKey, &Value)> = self.entries.iter().collect();
+        entries.sort_by_key(|(key, _)| *key);
+        entries
+    }
+}
+
+/// Errors raised by the VM, or created by scripts with the `Error` native
+#[derive(Debug)]
+pub struct ObjError {
+    pub message: String,
+    /// Innermost call first, filled in when the error is thrown
+    pub stack_trace: Vec<String>,
+}
+
+impl ObjError {
+    pub fn new(message: String) -> ObjError {
+        ObjError {
+            message,
+            stack_trace: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arity_range() {
+        let mut function = ObjFunction::new();
+        function.min_arity = 1;
+        function.max_arity = 2;
+        assert_eq!(function.arity_range(), "1 to 2");
+        assert!(function.accepts(2));
+        assert!(!function.accepts(3));
+        function.variadic = true;
+        assert_eq!(function.arity_range(), "at least 1");
+        assert!(function.accepts(3));
+        assert!(!function.accepts(0));
+    }
+
+    #[test]
+    fn test_module_name() {
+        let module = ObjModule::new(PathBuf::from("/scripts/lib/math.lox"));
+        assert_eq!(module.name(), "math");
+        assert_eq!(module.to_string(), "<module math>");
+    }
+
+    #[test]
+    fn test_map_key_integer_and_number() {
+        let integer = MapKey::Integer(i64::MAX);
+        let number = MapKey::from_value(&Value::Number(9223372036854775808.0)).unwrap();
+        assert_ne!(integer, number);
+        assert_eq!(integer.cmp(&number), Ordering::Less);
+        assert_eq!(number.cmp(&integer), Ordering::Greater);
+        let mut map = ObjMap::new();
+        map.entries.insert(integer, Value::Nil);
+        map.entries.insert(number, Value::Nil);
+        assert_eq!(map.entries.len(), 2);
+        assert!(MapKey::Integer(1) < MapKey::Number(1.5));
+        assert!(MapKey::Integer(-2) < MapKey::Number(-1.5));
+    }
+
+    #[test]
+    fn test_map_key_from_value() {
+        let key = MapKey::from_value(&Value::LString("a".to_string()));
+        assert_eq!(key, Some(MapKey::LString("a".to_string())));
+        let key = MapKey::from_value(&Value::Number(-0.0));
+        assert_eq!(key, Some(MapKey::Integer(0)));
+        let key = MapKey::from_value(&Value::Number(1.5));
+        assert_eq!(key, Some(MapKey::Number(1.5)));
+        let list = Obj::List(Rc::new(RefCell::new(ObjList::new(Vec::new()))));
+        assert_eq!(MapKey::from_value(&Value::Obj(list)), None);
+    }
+
+    #[test]
+    fn test_iterator() {
+        let mut iterator = ObjIterator::Range {
+            next: 3,
+            end: 0,
+            step: -2,
+        };
+        let values: Vec<String> = iterator.by_ref().map(|value| value.to_string()).collect();
+        assert_eq!(values, vec!["3", "1"]);
+        assert!(iterator.is_done());
+
+        let iterator = ObjIterator::Chars {
+            string: "añ".to_string(),
+            offset: 0,
+        };
+        assert_eq!(iterator.count(), 2);
+    }
+
+    #[test]
+    fn test_sorted_entries() {
+        let mut map = ObjMap::new();
+        map.entries
+            .insert(MapKey::LString("b".to_string()), Value::Nil);
+        map.entries
+            .insert(MapKey::LString("a".to_string()), Value::Nil);
+        map.entries.insert(MapKey::Integer(10), Value::Nil);
+        map.entries.insert(MapKey::Number(2.5), Value::Nil);
+        map.entries.insert(MapKey::Bool(true), Value::Nil);
+        map.entries.insert(MapKey::Nil, Value::Nil);
+        let keys: Vec<MapKey> = map
+            .sorted_entries()
+            .into_iter()
+            .map(|(key, _)| key.clone())
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                MapKey::Nil,
+                MapKey::Bool(true),
+                MapKey::Number(2.5),
+                MapKey::Integer(10),
+                MapKey::LString("a".to_string()),
+                MapKey::LString("b".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_list_to_string() {
+        let list = Rc::new(RefCell::new(ObjList::new(vec![
+            Value::Number(1.0),
+            Value::LString("1".to_string()),
+        ])));
+        let item = Value::Obj(Obj::List(list.clone()));
+        list.borrow_mut().items.push(item);
+        let list = Obj::List(list);
+        assert_eq!(list.to_string(), "[1, \"1\", [...]]");
+    }
+
+    #[test]
+    fn test_map_to_string() {
+        let mut map = ObjMap::new();
+        map.entries
+            .insert(MapKey::LString("b".to_string()), Value::Number(2.0));
+        map.entries
+            .insert(MapKey::LString("a".to_string()), Value::Number(1.0));
+        let map = Obj::Map(Rc::new(RefCell::new(map)));
+        assert_eq!(map.to_string(), "{\"a\": 1, \"b\": 2}");
+        if let Obj::Map(entries) = &map {
+            let value = Value::Obj(map.clone());
+            entries.borrow_mut().entries.insert(MapKey::Nil, value);
         }
+        assert_eq!(map.to_string(), "{nil: {...}, \"a\": 1, \"b\": 2}");
     }
 }
@@ -1,38 +1,85 @@
-use std::{collections::HashMap, time::SystemTime};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
 
 use crate::{
     chunk::OpCode,
     compiler::compile,
-    disassembler,
+    disassembler, native,
     value::{
-        object::{NativeFunction, Obj, ObjFunction, ObjNative},
+        object::{
+            CoroutineState, MapKey, NativeFunction, Obj, ObjBoundMethod, ObjBoundNative, ObjClass,
+            ObjClosure, ObjCoroutine, ObjError, ObjInstance, ObjIterator, ObjList, ObjMap,
+            ObjModule, ObjNative, ObjTuple, ObjUpvalue,
+        },
         Value,
     },
 };
 
 #[derive(Debug)]
 struct CallFrame {
-    function: ObjFunction,
+    closure: Rc<ObjClosure>,
     /// Instruction Pointer
     ip: usize,
     /// Index of the beginning of this frame on stack
     frame_pointer: usize,
+    /// Number of arguments the caller passed
+    arg_count: usize,
+    /// Try blocks the frame is currently inside of, innermost last
+    handlers: Vec<ExceptionHandler>,
 }
 
 impl CallFrame {
-    fn new(function: ObjFunction, frame_pointer: usize) -> Self {
+    fn new(closure: Rc<ObjClosure>, frame_pointer: usize, arg_count: usize) -> Self {
         CallFrame {
-            function,
+            closure,
             frame_pointer,
+            arg_count,
             ip: 0,
+            handlers: Vec::new(),
         }
     }
 }
 
+#[derive(Debug)]
+struct ExceptionHandler {
+    /// Address of the code handling the exception
+    catch_address: usize,
+    /// Stack size when entering the try block, which is restored before the handler runs
+    stack_len: usize,
+}
+
+/// The stack and the frames of a coroutine while it's suspended
+#[derive(Debug)]
+pub struct ExecutionContext {
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    /// Upvalues pointing into the stack with their slots.
+    /// They are closed while the context is suspended since the stack isn't the VM's stack.
+    upvalues: Vec<(usize, Rc<RefCell<ObjUpvalue>>)>,
+}
+
 pub struct VM {
     stack: Vec<Value>,
     frames: Vec<CallFrame>,
-    globals: HashMap<String, Value>,
+    /// Suspended contexts which resumed the running coroutines, outermost first,
+    /// along with the coroutine each of them resumed
+    resumers: Vec<(ExecutionContext, Rc<RefCell<ObjCoroutine>>)>,
+    /// Native functions, which are visible in every module
+    builtins: HashMap<String, Value>,
+    /// Module of the script passed to `interpret`
+    main_module: Rc<ObjModule>,
+    /// Imported modules by canonical path
+    modules: HashMap<PathBuf, Rc<ObjModule>>,
+    /// Upvalues which still point to variables on the stack
+    open_upvalues: Vec<Rc<RefCell<ObjUpvalue>>>,
+    /// Compile scripts and modules without assert statements
+    strip_asserts: bool,
 }
 
 const DEBUG: bool = false;
@@ -41,19 +88,52 @@ const FRAMES_MAX: usize = 64;
 
 impl VM {
     pub fn new() -> VM {
-        let mut globals = HashMap::new();
-        globals.insert("clock".to_string(), Self::define_native(Self::clock));
-        VM {
+        let mut vm = VM {
             stack: Vec::with_capacity(STACK_MAX),
             frames: Vec::with_capacity(FRAMES_MAX),
-            globals,
-        }
+            resumers: Vec::new(),
+            builtins: HashMap::new(),
+            main_module: Rc::new(ObjModule::new(PathBuf::new())),
+            modules: HashMap::new(),
+            open_upvalues: Vec::new(),
+            strip_asserts: false,
+        };
+        vm.define_native("clock", 0..=0, native::clock);
+        vm.define_native("len", 1..=1, native::len);
+        vm.define_native("push", 2..=2, native::push);
+        vm.define_native("pop", 1..=1, native::pop);
+        vm.define_native("keys", 1..=1, native::keys);
+        vm.define_native("values", 1..=1, native::values);
+        vm.define_native("has", 2..=2, native::has);
+        vm.define_native("remove", 2..=2, native::remove);
+        vm.define_native("Error", 1..=1, native::error);
+        vm.define_native("int", 1..=1, native::int);
+        vm.define_native("float", 1..=1, native::float);
+        vm.define_native("range", 1..=3, native::range);
+        vm
     }
 
+    /// Runtime errors are thrown as error values so that scripts can catch them.
+    /// Errors which no handler catches abort the execution.
     fn run(&mut self) -> Result<(), InterpretError> {
+        loop {
+            match self.execute() {
+                Err(InterpretError::RuntimeError(message)) if self.has_handler() => {
+                    let mut error = ObjError::new(message);
+                    error.stack_trace = self.stack_trace();
+                    let error = Value::Obj(Obj::Error(Rc::new(RefCell::new(error))));
+                    self.throw(error)?;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn execute(&mut self) -> Result<(), InterpretError> {
         loop {
             let frame = self.frames.last_mut().unwrap();
-            let instruction = &frame.function.chunk.code[frame.ip];
+            let chunk = &frame.closure.function.chunk;
+            let instruction = chunk.code[frame.ip].clone();
             if DEBUG {
                 println!("      ");
                 for slot in self.stack.clone() {
@@ -61,7 +141,7 @@ impl VM {
                 }
                 disassembler::disassemble_instruction(
                     frame.ip,
-                    frame.function.chunk.lines[frame.ip],
+                    chunk.lines[frame.ip],
                     &instruction,
                 );
             }
@@ -70,10 +150,15 @@ impl VM {
                 OpCode::OpReturn => {
                     let result = self.stack.pop().unwrap();
                     let previous_frame_pointer = self.frames.pop().unwrap().frame_pointer;
+                    self.close_upvalues(previous_frame_pointer);
                     // discard the values the frame had
                     self.stack.drain(previous_frame_pointer..);
-                    if self.frames.len() == 0 {
-                        return Ok(());
+                    if self.frames.is_empty() {
+                        if self.resumers.is_empty() {
+                            return Ok(());
+                        }
+                        // the coroutine finished, so its resumer gets the returned value
+                        self.finish_coroutine();
                     }
                     self.stack.push(result);
                 }
@@ -82,14 +167,30 @@ impl VM {
                         self.stack.pop().unwrap();
                         self.stack.push(Value::Number(-number));
                     }
+                    Value::Integer(integer) => {
+                        let negated = integer.checked_neg().ok_or_else(integer_overflow)?;
+                        self.stack.pop().unwrap();
+                        self.stack.push(Value::Integer(negated));
+                    }
                     _ => {
                         let message = "Operand must be a number.".to_string();
                         let err = InterpretError::RuntimeError(message);
                         return Err(err);
                     }
                 },
+                OpCode::OpBitNot => match self.stack.pop().unwrap() {
+                    Value::Integer(integer) => self.stack.push(Value::Integer(!integer)),
+                    Value::Number(number) => {
+                        let integer = to_integer(number)?;
+                        self.stack.push(Value::Integer(!integer));
+                    }
+                    _ => {
+                        let message = "Operand must be a number.".to_string();
+                        return Err(InterpretError::RuntimeError(message));
+                    }
+                },
                 OpCode::OpConstant { index } => {
-                    let constant = frame.function.chunk.constants[*index].clone();
+                    let constant = chunk.constants[index].clone();
                     self.stack.push(constant);
                 }
                 OpCode::OpNil => self.stack.push(Value::Nil),
@@ -108,17 +209,29 @@ impl VM {
                 OpCode::OpPop => {
                     self.stack.pop();
                 }
-                OpCode::OpDefineGlobal { index } => {
-                    let name = frame.function.chunk.constants[*index].clone().as_string();
-                    let value = self.stack.last().unwrap();
-                    self.globals.insert(name, value.clone());
-                    self.stack.pop();
+                OpCode::OpDefineGlobal { index } | OpCode::OpDefineConstant { index } => {
+                    let name = chunk.constants[index].as_string();
+                    let module = &frame.closure.module;
+                    let mut constants = module.constants.borrow_mut();
+                    if constants.contains(&name) {
+                        let message = format!("Can't redefine constant '{name}'.");
+                        return Err(InterpretError::RuntimeError(message));
+                    }
+                    if matches!(instruction, OpCode::OpDefineConstant { .. }) {
+                        constants.insert(name.clone());
+                    }
+                    let value = self.stack.pop().unwrap();
+                    module.globals.borrow_mut().insert(name, value);
                 }
                 OpCode::OpGetGlobal { index } => {
-                    let name = frame.function.chunk.constants[*index].clone().as_string();
-                    match self.globals.get(&name) {
+                    let name = chunk.constants[index].as_string();
+                    let value = match frame.closure.module.globals.borrow().get(&name) {
+                        Some(value) => Some(value.clone()),
+                        None => self.builtins.get(&name).cloned(),
+                    };
+                    match value {
                         Some(value) => {
-                            self.stack.push(value.clone());
+                            self.stack.push(value);
                         }
                         _ => {
                             let message = format!("Undefined variable '{}'", name);
@@ -131,49 +244,526 @@ impl VM {
                     .stack
                     .push(self.stack[frame.frame_pointer + index].clone()),
                 OpCode::OpSetGlobal { index } => {
-                    let name = frame.function.chunk.constants[*index].clone().as_string();
+                    let name = chunk.constants[index].as_string();
+                    if frame.closure.module.constants.borrow().contains(&name) {
+                        let message = format!("Can't assign to constant '{name}'.");
+                        return Err(InterpretError::RuntimeError(message));
+                    }
                     let value = self.stack.last().unwrap().clone();
-                    match self.globals.insert(name.clone(), value) {
-                        None => {
-                            self.globals.remove(&name);
-                            let message = format!("Undefined variable '{}'", name);
-                            let err = InterpretError::RuntimeError(message);
-                            return Err(err);
-                        }
-                        _ => {}
+                    let mut globals = frame.closure.module.globals.borrow_mut();
+                    if globals.insert(name.clone(), value).is_none() {
+                        globals.remove(&name);
+                        let message = format!("Undefined variable '{}'", name);
+                        let err = InterpretError::RuntimeError(message);
+                        return Err(err);
                     }
                 }
                 OpCode::OpSetLocal { index } => {
                     self.stack[frame.frame_pointer + index] = self.stack.last().unwrap().clone();
                 }
+                OpCode::OpGetUpvalue { index } => {
+                    let upvalue = frame.closure.upvalues[index].clone();
+                    let value = match &*upvalue.borrow() {
+                        ObjUpvalue::Open(slot) => self.stack[*slot].clone(),
+                        ObjUpvalue::Closed(value) => value.clone(),
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::OpSetUpvalue { index } => {
+                    let upvalue = frame.closure.upvalues[index].clone();
+                    let value = self.stack.last().unwrap().clone();
+                    match &mut *upvalue.borrow_mut() {
+                        ObjUpvalue::Open(slot) => self.stack[*slot] = value,
+                        ObjUpvalue::Closed(closed) => *closed = value,
+                    };
+                }
+                OpCode::OpCloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.stack.pop();
+                }
+                OpCode::OpClosure { index } => {
+                    let function = match &chunk.constants[index] {
+                        Value::Obj(Obj::Function(function)) => function.clone(),
+                        _ => panic!("Expected function"),
+                    };
+                    let frame_pointer = frame.frame_pointer;
+                    let enclosing_upvalues = frame.closure.upvalues.clone();
+                    let module = frame.closure.module.clone();
+                    let upvalues = function
+                        .upvalues
+                        .iter()
+                        .map(|upvalue| {
+                            if upvalue.is_local {
+                                self.capture_upvalue(frame_pointer + upvalue.index)
+                            } else {
+                                enclosing_upvalues[upvalue.index].clone()
+                            }
+                        })
+                        .collect();
+                    let closure = ObjClosure::new(function, upvalues, module);
+                    self.stack.push(Value::Obj(Obj::Closure(Rc::new(closure))));
+                }
+                OpCode::OpClass { index } => {
+                    let name = chunk.constants[index].as_string();
+                    let class = ObjClass::new(name);
+                    self.stack
+                        .push(Value::Obj(Obj::Class(Rc::new(RefCell::new(class)))));
+                }
+                OpCode::OpMethod { index } => {
+                    let name = chunk.constants[index].as_string();
+                    let method = match self.stack.pop().unwrap() {
+                        Value::Obj(Obj::Closure(closure)) => closure,
+                        _ => panic!("Expected method closure"),
+                    };
+                    match self.stack.last().unwrap() {
+                        Value::Obj(Obj::Class(class)) => {
+                            class.borrow_mut().methods.insert(name, method);
+                        }
+                        _ => panic!("Expected class"),
+                    }
+                }
+                OpCode::OpGetProperty { index } => {
+                    let name = chunk.constants[index].as_string();
+                    let instance = match self.stack.last().unwrap() {
+                        Value::Obj(Obj::Instance(instance)) => instance.clone(),
+                        Value::Obj(Obj::Error(error)) => {
+                            let value = error_property(&error.borrow(), &name)?;
+                            self.stack.pop();
+                            self.stack.push(value);
+                            continue;
+                        }
+                        Value::Obj(Obj::Module(module)) => {
+                            let value = module_export(module, &name)?;
+                            self.stack.pop();
+                            self.stack.push(value);
+                            continue;
+                        }
+                        Value::Obj(Obj::Coroutine(coroutine)) => {
+                            let value = coroutine_property(coroutine, &name)?;
+                            self.stack.pop();
+                            self.stack.push(value);
+                            continue;
+                        }
+                        Value::LString(_) => {
+                            let Some(method) = native::string_method(&name) else {
+                                let message = format!("Undefined property '{name}'.");
+                                return Err(InterpretError::RuntimeError(message));
+                            };
+                            let receiver = self.stack.pop().unwrap();
+                            let bound_native = ObjBoundNative::new(receiver, method);
+                            self.stack
+                                .push(Value::Obj(Obj::BoundNative(Rc::new(bound_native))));
+                            continue;
+                        }
+                        _ => {
+                            let message = "Only instances have properties.".to_string();
+                            return Err(InterpretError::RuntimeError(message));
+                        }
+                    };
+                    // Fields shadow methods
+                    let field = instance.borrow().fields.get(&name).cloned();
+                    if let Some(value) = field {
+                        self.stack.pop();
+                        self.stack.push(value);
+                        continue;
+                    }
+                    let class = instance.borrow().class.clone();
+                    self.bind_method(class, &name)?;
+                }
+                OpCode::OpSetProperty { index } => {
+                    let name = chunk.constants[index].as_string();
+                    let stack_len = self.stack.len();
+                    let instance = match &self.stack[stack_len - 2] {
+                        Value::Obj(Obj::Instance(instance)) => instance.clone(),
+                        _ => {
+                            let message = "Only instances have fields.".to_string();
+                            return Err(InterpretError::RuntimeError(message));
+                        }
+                    };
+                    let value = self.stack.pop().unwrap();
+                    instance.borrow_mut().fields.insert(name, value.clone());
+                    // replace the instance with the assigned value
+                    self.stack.pop();
+                    self.stack.push(value);
+                }
+                OpCode::OpInherit => {
+                    let stack_len = self.stack.len();
+                    let superclass = match &self.stack[stack_len - 2] {
+                        Value::Obj(Obj::Class(superclass)) => superclass.clone(),
+                        _ => {
+                            let message = "Superclass must be a class.".to_string();
+                            return Err(InterpretError::RuntimeError(message));
+                        }
+                    };
+                    let subclass = match self.stack.pop().unwrap() {
+                        Value::Obj(Obj::Class(subclass)) => subclass,
+                        _ => panic!("Expected class"),
+                    };
+                    // Copy down the inherited methods.
+                    // Methods declared in the subclass override them afterwards.
+                    let methods = superclass.borrow().methods.clone();
+                    subclass.borrow_mut().methods.extend(methods);
+                }
+                OpCode::OpGetSuper { index } => {
+                    let name = chunk.constants[index].as_string();
+                    let superclass = match self.stack.pop().unwrap() {
+                        Value::Obj(Obj::Class(superclass)) => superclass,
+                        _ => panic!("Expected superclass"),
+                    };
+                    self.bind_method(superclass, &name)?;
+                }
+                OpCode::OpBuildList { item_count } => {
+                    let items_start = self.stack.len() - item_count;
+                    let items = self.stack.split_off(items_start);
+                    let list = ObjList::new(items);
+                    self.stack
+                        .push(Value::Obj(Obj::List(Rc::new(RefCell::new(list)))));
+                }
+                OpCode::OpBuildMap { entry_count } => {
+                    let entries_start = self.stack.len() - entry_count * 2;
+                    let entries = self.stack.split_off(entries_start);
+                    let mut map = ObjMap::new();
+                    for entry in entries.chunks(2) {
+                        let key = map_key(&entry[0])?;
+                        map.entries.insert(key, entry[1].clone());
+                    }
+                    self.stack
+                        .push(Value::Obj(Obj::Map(Rc::new(RefCell::new(map)))));
+                }
+                OpCode::OpBuildTuple { item_count } => {
+                    let items = self.stack.split_off(self.stack.len() - item_count);
+                    let tuple = ObjTuple::new(items);
+                    self.stack.push(Value::Obj(Obj::Tuple(Rc::new(tuple))));
+                }
+                OpCode::OpUnpack { count } => {
+                    let tuple = match self.stack.pop().unwrap() {
+                        Value::Obj(Obj::Tuple(tuple)) => tuple,
+                        _ => {
+                            let message = "Only tuples can be unpacked.".to_string();
+                            return Err(InterpretError::RuntimeError(message));
+                        }
+                    };
+                    if tuple.items.len() != count {
+                        let message = format!(
+                            "Expected {count} values to unpack but got {}.",
+                            tuple.items.len()
+                        );
+                        return Err(InterpretError::RuntimeError(message));
+                    }
+                    self.stack.extend(tuple.items.iter().cloned());
+                }
+                OpCode::OpGetIndex => {
+                    let index = self.stack.pop().unwrap();
+                    let target = self.stack.pop().unwrap();
+                    let value = get_index(&target, &index)?;
+                    self.stack.push(value);
+                }
+                OpCode::OpSetIndex => {
+                    let value = self.stack.pop().unwrap();
+                    let index = self.stack.pop().unwrap();
+                    let target = self.stack.pop().unwrap();
+                    set_index(&target, &index, value.clone())?;
+                    self.stack.push(value);
+                }
+                OpCode::OpGetSlice => {
+                    let end = self.stack.pop().unwrap();
+                    let start = self.stack.pop().unwrap();
+                    let target = self.stack.pop().unwrap();
+                    let value = get_slice(&target, &start, &end)?;
+                    self.stack.push(value);
+                }
+                OpCode::OpToString => {
+                    let value = self.stack.pop().unwrap();
+                    let string = match value {
+                        Value::LString(string) => string,
+                        _ => value.to_string(),
+                    };
+                    self.stack.push(Value::LString(string));
+                }
                 OpCode::OpJumpIfFalse { offset } => {
                     let value = self.stack.last().unwrap().clone();
                     if is_falsey(value) {
                         frame.ip += offset;
                     }
                 }
+                OpCode::OpJumpIfNil { offset } => {
+                    if let Value::Nil = self.stack.last().unwrap() {
+                        frame.ip += offset;
+                    }
+                }
                 OpCode::OpJump { offset } => {
                     frame.ip += offset;
                 }
+                OpCode::OpJumpIfPassed { index, offset } => {
+                    // skip the default value when the argument was passed
+                    if index < frame.arg_count {
+                        frame.ip += offset;
+                    }
+                }
+                OpCode::OpJumpTable { index } => {
+                    let value = self.stack.pop().unwrap();
+                    frame.ip += chunk.jump_tables[index].offset(&value);
+                }
                 OpCode::OpJumpBack { offset } => {
                     frame.ip -= offset;
                 }
+                OpCode::OpGetIterator => {
+                    let value = self.stack.pop().unwrap();
+                    self.stack.push(get_iterator(value)?);
+                }
+                OpCode::OpIterate { index } => {
+                    let iterator = self.stack[frame.frame_pointer + index].clone();
+                    self.iterate(iterator)?;
+                }
+                OpCode::OpJumpIfExhausted { index, offset } => {
+                    let exhausted = match &self.stack[frame.frame_pointer + index] {
+                        Value::Obj(Obj::Iterator(iterator)) => iterator.borrow().is_done(),
+                        Value::Obj(Obj::Coroutine(coroutine)) => coroutine.borrow().is_done(),
+                        // the next() method of other iterators returns nil at the end
+                        _ => matches!(self.stack.last().unwrap(), Value::Nil),
+                    };
+                    if exhausted {
+                        self.stack.pop();
+                        frame.ip += offset;
+                    }
+                }
+                OpCode::OpTry { offset } => {
+                    let handler = ExceptionHandler {
+                        catch_address: frame.ip + offset,
+                        stack_len: self.stack.len(),
+                    };
+                    frame.handlers.push(handler);
+                }
+                OpCode::OpEndTry => {
+                    frame.handlers.pop();
+                }
+                OpCode::OpImport { index } => {
+                    let path = chunk.constants[index].as_string();
+                    let importer = frame.closure.module.clone();
+                    self.import(&importer, &path)?;
+                }
+                OpCode::OpEndModule => {
+                    let module = frame.closure.module.clone();
+                    module.loaded.set(true);
+                    self.stack.push(Value::Obj(Obj::Module(module)));
+                }
+                OpCode::OpAssert { index } => {
+                    let mut message = chunk.constants[index].to_string();
+                    let detail = self.stack.pop().unwrap();
+                    if !matches!(detail, Value::Nil) {
+                        message = format!("{message} ({detail})");
+                    }
+                    return Err(InterpretError::RuntimeError(message));
+                }
+                OpCode::OpThrow => {
+                    let exception = self.stack.pop().unwrap();
+                    self.throw(exception)?;
+                }
+                OpCode::OpYield => {
+                    let value = self.stack.pop().unwrap();
+                    let context = self.suspend_context();
+                    let (resumer, coroutine) = self.resumers.pop().unwrap();
+                    coroutine.borrow_mut().state = CoroutineState::Suspended(context);
+                    self.restore_context(resumer);
+                    self.stack.push(value);
+                }
                 OpCode::OpAdd
                 | OpCode::OpSubtract
                 | OpCode::OpMultiply
                 | OpCode::OpDivide
+                | OpCode::OpModulo
+                | OpCode::OpPower
+                | OpCode::OpBitAnd
+                | OpCode::OpBitOr
+                | OpCode::OpBitXor
+                | OpCode::OpShiftLeft
+                | OpCode::OpShiftRight
                 | OpCode::OpGreater
                 | OpCode::OpLess => {
-                    Self::binary_operation(&mut self.stack, instruction)?;
+                    Self::binary_operation(&mut self.stack, &instruction)?;
                 }
                 OpCode::OpCall { arg_count } => {
                     let function = self.stack[self.stack.len() - 1 - arg_count].clone();
-                    let arg_count = arg_count.clone();
-                    let ip = frame.ip;
-                    self.call_value(function, arg_count, ip)?;
+                    self.call_value(function, arg_count)?;
+                }
+            }
+        }
+    }
+
+    /// Push the module at `path`, which is relative to the importing module.
+    /// A module imported for the first time is run before it's pushed.
+    fn import(&mut self, importer: &ObjModule, path: &str) -> Result<(), InterpretError> {
+        let directory = importer.path.parent().unwrap_or(Path::new("."));
+        let canonical_path = directory.join(path).canonicalize().map_err(|_| {
+            let message = format!("Could not find module '{path}'.");
+            InterpretError::RuntimeError(message)
+        })?;
+        if let Some(module) = self.modules.get(&canonical_path) {
+            if !module.loaded.get() {
+                let message =
+                    format!("Import cycle: module '{path}' is imported while it's still loading.");
+                return Err(InterpretError::RuntimeError(message));
+            }
+            self.stack.push(Value::Obj(Obj::Module(module.clone())));
+            return Ok(());
+        }
+
+        let source = fs::read_to_string(&canonical_path).map_err(|_| {
+            let message = format!("Could not read module '{path}'.");
+            InterpretError::RuntimeError(message)
+        })?;
+        let function = compile(&source, self.strip_asserts).map_err(|_| {
+            let message = format!("Could not compile module '{path}'.");
+            InterpretError::RuntimeError(message)
+        })?;
+        let module = Rc::new(ObjModule::new(canonical_path.clone()));
+        self.modules.insert(canonical_path, module.clone());
+        // The script of the module returns the module when it ends
+        let closure = Rc::new(ObjClosure::new(Rc::new(function), Vec::new(), module));
+        self.stack.push(Value::Obj(Obj::Closure(closure.clone())));
+        self.call(closure, 0)
+    }
+
+    /// Imports in the main script are resolved relative to its path
+    pub fn set_main_path(&mut self, path: &Path) {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let module = Rc::new(ObjModule::new(path.clone()));
+        self.modules.insert(path, module.clone());
+        self.main_module = module;
+    }
+
+    pub fn set_strip_asserts(&mut self, strip_asserts: bool) {
+        self.strip_asserts = strip_asserts;
+    }
+
+    /// The number of frames in the running context and all of the resumers
+    fn frame_count(&self) -> usize {
+        self.frames.len()
+            + self
+                .resumers
+                .iter()
+                .map(|(resumer, _)| resumer.frames.len())
+                .sum::<usize>()
+    }
+
+    /// Whether the running context or any of the resumers is inside a try block
+    fn has_handler(&self) -> bool {
+        self.frames
+            .iter()
+            .chain(
+                self.resumers
+                    .iter()
+                    .flat_map(|(resumer, _)| &resumer.frames),
+            )
+            .any(|frame| !frame.handlers.is_empty())
+    }
+
+    /// Unwind the frames and the stack to the innermost handler
+    /// and continue from there with the exception on top of the stack.
+    /// Returns an error when no handler catches the exception.
+    fn throw(&mut self, exception: Value) -> Result<(), InterpretError> {
+        if let Value::Obj(Obj::Error(error)) = &exception {
+            if error.borrow().stack_trace.is_empty() {
+                error.borrow_mut().stack_trace = self.stack_trace();
+            }
+        }
+        if !self.has_handler() {
+            let message = match &exception {
+                Value::Obj(Obj::Error(error)) => error.borrow().message.clone(),
+                _ => format!("Uncaught exception: {exception}"),
+            };
+            return Err(InterpretError::RuntimeError(message));
+        }
+        // the exception finishes the coroutines which don't catch it
+        let frame_index = loop {
+            let handler_frame = self
+                .frames
+                .iter()
+                .rposition(|frame| !frame.handlers.is_empty());
+            match handler_frame {
+                Some(frame_index) => break frame_index,
+                None => {
+                    self.forget_loading_modules(0);
+                    self.finish_coroutine();
                 }
             }
+        };
+        self.forget_loading_modules(frame_index + 1);
+        self.frames.truncate(frame_index + 1);
+        let frame = self.frames.last_mut().unwrap();
+        let handler = frame.handlers.pop().unwrap();
+        frame.ip = handler.catch_address;
+        self.close_upvalues(handler.stack_len);
+        self.stack.truncate(handler.stack_len);
+        self.stack.push(exception);
+        Ok(())
+    }
+
+    /// Remove the modules whose scripts are in the frames from `frame_index` on from the cache.
+    /// Their loading is aborted by an exception, so importing them again runs them again
+    /// instead of reporting an import cycle.
+    fn forget_loading_modules(&mut self, frame_index: usize) {
+        for frame in &self.frames[frame_index..] {
+            let module = &frame.closure.module;
+            if frame.closure.function.script
+                && !module.loaded.get()
+                && !Rc::ptr_eq(module, &self.main_module)
+            {
+                self.modules.remove(&module.path);
+            }
+        }
+    }
+
+    /// Replace the instance on top of the stack with its method bound to it.
+    fn bind_method(
+        &mut self,
+        class: Rc<RefCell<ObjClass>>,
+        name: &str,
+    ) -> Result<(), InterpretError> {
+        let method = match class.borrow().methods.get(name) {
+            Some(method) => method.clone(),
+            None => {
+                let message = format!("Undefined property '{name}'.");
+                return Err(InterpretError::RuntimeError(message));
+            }
+        };
+        let receiver = self.stack.pop().unwrap();
+        let bound_method = ObjBoundMethod::new(receiver, method);
+        self.stack
+            .push(Value::Obj(Obj::BoundMethod(Rc::new(bound_method))));
+        Ok(())
+    }
+
+    /// Returns the upvalue pointing to the stack slot.
+    /// Closures capturing the same variable share the same upvalue.
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<ObjUpvalue>> {
+        let existing = self
+            .open_upvalues
+            .iter()
+            .find(|upvalue| matches!(*upvalue.borrow(), ObjUpvalue::Open(open) if open == slot));
+        if let Some(upvalue) = existing {
+            return upvalue.clone();
         }
+        let upvalue = Rc::new(RefCell::new(ObjUpvalue::Open(slot)));
+        self.open_upvalues.push(upvalue.clone());
+        upvalue
+    }
+
+    /// Move the variables at or above the stack slot `last` into their upvalues
+    /// since they are about to be discarded from the stack.
+    fn close_upvalues(&mut self, last: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let slot = match *upvalue.borrow() {
+                ObjUpvalue::Open(slot) => slot,
+                ObjUpvalue::Closed(_) => return false,
+            };
+            if slot < last {
+                return true;
+            }
+            *upvalue.borrow_mut() = ObjUpvalue::Closed(stack[slot].clone());
+            false
+        });
     }
 
     fn binary_operation(
@@ -182,12 +772,34 @@ impl VM {
     ) -> Result<(), InterpretError> {
         let stack_len = stack.len();
         match (&stack[stack_len - 1], &stack[stack_len - 2]) {
-            (Value::Number(right), Value::Number(left)) => {
+            (Value::Integer(right), Value::Integer(left)) => {
+                let result = integer_operation(*left, *right, binary_operator)?;
+                stack.pop().unwrap();
+                stack.pop().unwrap();
+                stack.push(result);
+                Ok(())
+            }
+            // mixing integers and floats promotes both operands to float
+            (
+                right @ (Value::Integer(_) | Value::Number(_)),
+                left @ (Value::Integer(_) | Value::Number(_)),
+            ) => {
+                let (left, right) = (&left.as_number(), &right.as_number());
                 let result = match binary_operator {
                     OpCode::OpAdd => Value::Number(left + right),
                     OpCode::OpSubtract => Value::Number(left - right),
                     OpCode::OpMultiply => Value::Number(left * right),
                     OpCode::OpDivide => Value::Number(left / right),
+                    OpCode::OpModulo => Value::Number(left % right),
+                    OpCode::OpPower => Value::Number(left.powf(*right)),
+                    OpCode::OpBitAnd
+                    | OpCode::OpBitOr
+                    | OpCode::OpBitXor
+                    | OpCode::OpShiftLeft
+                    | OpCode::OpShiftRight => {
+                        let (left, right) = (to_integer(*left)?, to_integer(*right)?);
+                        Value::Integer(bitwise_operation(left, right, binary_operator)?)
+                    }
                     OpCode::OpGreater => Value::Bool(left > right),
                     OpCode::OpLess => Value::Bool(left < right),
                     _ => panic!("We got {binary_operator:?}."),
@@ -195,7 +807,7 @@ impl VM {
                 stack.pop().unwrap();
                 stack.pop().unwrap();
                 stack.push(result);
-                return Ok(());
+                Ok(())
             }
             (Value::LString(right), Value::LString(left)) => {
                 let result = match binary_operator {
@@ -203,6 +815,13 @@ impl VM {
                     OpCode::OpSubtract
                     | OpCode::OpMultiply
                     | OpCode::OpDivide
+                    | OpCode::OpModulo
+                    | OpCode::OpPower
+                    | OpCode::OpBitAnd
+                    | OpCode::OpBitOr
+                    | OpCode::OpBitXor
+                    | OpCode::OpShiftLeft
+                    | OpCode::OpShiftRight
                     | OpCode::OpGreater
                     | OpCode::OpLess => {
                         let message = "You cannot use that operator for strings.".to_string();
@@ -214,86 +833,314 @@ impl VM {
                 stack.pop().unwrap();
                 stack.pop().unwrap();
                 stack.push(result);
-                return Ok(());
+                Ok(())
             }
             (_, _) => {
                 let message = "Operands must be two numbers or two strings.".to_string();
                 let err = InterpretError::RuntimeError(message);
-                return Err(err);
+                Err(err)
             }
         }
     }
 
-    fn call_value(
-        &mut self,
-        callee: Value,
-        arg_count: usize,
-        ip: usize,
-    ) -> Result<(), InterpretError> {
+    fn call_value(&mut self, callee: Value, arg_count: usize) -> Result<(), InterpretError> {
         if let Value::Obj(obj) = callee {
             match obj {
-                Obj::Function(function) => {
-                    return self.call(function, arg_count);
+                Obj::Closure(closure) => {
+                    return self.call(closure, arg_count);
+                }
+                Obj::Class(class) => {
+                    let initializer = class.borrow().methods.get("init").cloned();
+                    let instance = ObjInstance::new(class);
+                    let callee_slot = self.stack.len() - 1 - arg_count;
+                    // the class on the stack is replaced with the new instance
+                    // so that the initializer can find it in slot 0.
+                    self.stack[callee_slot] =
+                        Value::Obj(Obj::Instance(Rc::new(RefCell::new(instance))));
+                    if let Some(initializer) = initializer {
+                        return self.call(initializer, arg_count);
+                    }
+                    if arg_count != 0 {
+                        let message = format!("Expected 0 arguments but got {arg_count}.");
+                        return Err(InterpretError::RuntimeError(message));
+                    }
+                    return Ok(());
+                }
+                Obj::BoundMethod(bound_method) => {
+                    let callee_slot = self.stack.len() - 1 - arg_count;
+                    // the receiver becomes `this` in slot 0
+                    self.stack[callee_slot] = bound_method.receiver.clone();
+                    return self.call(bound_method.method.clone(), arg_count);
                 }
                 Obj::NativeFunction(function) => {
+                    if !function.accepts(arg_count) {
+                        let arity = function.arity_range();
+                        let message = format!("Expected {arity} arguments but got {arg_count}.");
+                        return Err(InterpretError::RuntimeError(message));
+                    }
+                    let args_start = self.stack.len() - arg_count;
                     let native_function = function.native_function;
-                    let result = native_function(arg_count, ip);
-                    let stack_tail = self.stack.len() - 1;
+                    let result = native_function(&self.stack[args_start..])
+                        .map_err(InterpretError::RuntimeError)?;
                     // remove argument values and function from stack
-                    self.stack.drain((stack_tail - arg_count)..);
+                    self.stack.drain((args_start - 1)..);
+                    self.stack.push(result);
+                    return Ok(());
+                }
+                Obj::Resume(coroutine) => {
+                    if arg_count > 1 {
+                        let message = format!("Expected 0 to 1 arguments but got {arg_count}.");
+                        return Err(InterpretError::RuntimeError(message));
+                    }
+                    let value = match arg_count {
+                        0 => Value::Nil,
+                        _ => self.stack.pop().unwrap(),
+                    };
+                    self.stack.pop();
+                    return self.resume(coroutine, value);
+                }
+                Obj::BoundNative(bound_native) => {
+                    if !bound_native.native.accepts(arg_count) {
+                        let arity = bound_native.native.arity_range();
+                        let message = format!("Expected {arity} arguments but got {arg_count}.");
+                        return Err(InterpretError::RuntimeError(message));
+                    }
+                    // the receiver takes the place of the callee as the first argument
+                    let callee_slot = self.stack.len() - 1 - arg_count;
+                    self.stack[callee_slot] = bound_native.receiver.clone();
+                    let native_function = bound_native.native.native_function;
+                    let result = native_function(&self.stack[callee_slot..])
+                        .map_err(InterpretError::RuntimeError)?;
+                    self.stack.drain(callee_slot..);
                     self.stack.push(result);
                     return Ok(());
                 }
+                _ => (),
             }
         }
         let message = "Can only call functions and classes.".to_string();
         let err = InterpretError::RuntimeError(message);
-        return Err(err);
+        Err(err)
     }
 
-    fn call(&mut self, function: ObjFunction, arg_count: usize) -> Result<(), InterpretError> {
-        let arity = function.arity;
-        if arg_count != arity {
+    fn call(&mut self, closure: Rc<ObjClosure>, arg_count: usize) -> Result<(), InterpretError> {
+        let function = &closure.function;
+        if !function.accepts(arg_count) {
+            let arity = function.arity_range();
             let message = format!("Expected {arity} arguments but got {arg_count}.");
             let err = InterpretError::RuntimeError(message);
             return Err(err);
         }
-        if self.frames.len() == FRAMES_MAX {
+        if self.frame_count() >= FRAMES_MAX {
             let message = "Stack overflow.".to_string();
             let err = InterpretError::RuntimeError(message);
             return Err(err);
         }
+        // missing arguments are nil until the function evaluates their default values
+        for _ in arg_count..function.max_arity {
+            self.stack.push(Value::Nil);
+        }
+        let mut parameter_count = function.max_arity;
+        if function.variadic {
+            let extra_count = arg_count.saturating_sub(function.max_arity);
+            let rest: Vec<Value> = self.stack.drain(self.stack.len() - extra_count..).collect();
+            let rest = Obj::List(Rc::new(RefCell::new(ObjList::new(rest))));
+            self.stack.push(Value::Obj(rest));
+            parameter_count += 1;
+        }
         let stack_size = self.stack.len() - 1;
-        let frame = CallFrame::new(function, stack_size - arg_count);
+        let frame_pointer = stack_size - parameter_count;
+        if closure.function.generator {
+            // the callee and the arguments move to the stack of the new coroutine
+            let context = ExecutionContext {
+                stack: self.stack.split_off(frame_pointer),
+                frames: vec![CallFrame::new(closure.clone(), 0, arg_count)],
+                upvalues: Vec::new(),
+            };
+            let coroutine = ObjCoroutine::new(closure.function.name.clone(), context);
+            self.stack
+                .push(Value::Obj(Obj::Coroutine(Rc::new(RefCell::new(coroutine)))));
+            return Ok(());
+        }
+        let frame = CallFrame::new(closure, frame_pointer, arg_count);
         self.frames.push(frame);
         Ok(())
     }
 
-    fn define_native(function: NativeFunction) -> Value {
-        let obj_native = ObjNative::new(function);
-        let native_function = Obj::NativeFunction(obj_native);
-        Value::Obj(native_function)
+    /// Continue the coroutine until it yields or returns, which pushes the value for the caller.
+    /// The value passed to `resume` becomes the value of the `yield` the coroutine stopped at.
+    fn resume(
+        &mut self,
+        coroutine: Rc<RefCell<ObjCoroutine>>,
+        value: Value,
+    ) -> Result<(), InterpretError> {
+        // resuming nests the frames of the coroutine in those of the resumer
+        if self.frame_count() >= FRAMES_MAX {
+            let message = "Stack overflow.".to_string();
+            return Err(InterpretError::RuntimeError(message));
+        }
+        let state = std::mem::replace(&mut coroutine.borrow_mut().state, CoroutineState::Running);
+        let context = match state {
+            CoroutineState::Suspended(context) => context,
+            CoroutineState::Running => {
+                let message = "Can't resume a running coroutine.".to_string();
+                return Err(InterpretError::RuntimeError(message));
+            }
+            CoroutineState::Done => {
+                coroutine.borrow_mut().state = CoroutineState::Done;
+                let message = "Can't resume a finished coroutine.".to_string();
+                return Err(InterpretError::RuntimeError(message));
+            }
+        };
+        let resumer = self.suspend_context();
+        self.resumers.push((resumer, coroutine.clone()));
+        self.restore_context(context);
+        if coroutine.borrow().started {
+            self.stack.push(value);
+        }
+        coroutine.borrow_mut().started = true;
+        Ok(())
+    }
+
+    /// Push the next value of the iterator of a for-in loop.
+    /// Coroutines and iterators written in Lox push it once they yield or return.
+    fn iterate(&mut self, iterator: Value) -> Result<(), InterpretError> {
+        match &iterator {
+            Value::Obj(Obj::Iterator(native)) => {
+                let value = native.borrow_mut().next().unwrap_or(Value::Nil);
+                self.stack.push(value);
+                Ok(())
+            }
+            Value::Obj(Obj::Coroutine(coroutine)) if !coroutine.borrow().is_done() => {
+                self.resume(coroutine.clone(), Value::Nil)
+            }
+            Value::Obj(Obj::Coroutine(_)) => {
+                self.stack.push(Value::Nil);
+                Ok(())
+            }
+            Value::Obj(Obj::Instance(instance)) => {
+                // call `iterator.next()`
+                self.stack.push(iterator.clone());
+                let field = instance.borrow().fields.get("next").cloned();
+                if let Some(next) = field {
+                    *self.stack.last_mut().unwrap() = next.clone();
+                    return self.call_value(next, 0);
+                }
+                let method = instance
+                    .borrow()
+                    .class
+                    .borrow()
+                    .methods
+                    .get("next")
+                    .cloned();
+                match method {
+                    Some(next) => self.call(next, 0),
+                    None => {
+                        let message = "Iterators must have a 'next' method.".to_string();
+                        Err(InterpretError::RuntimeError(message))
+                    }
+                }
+            }
+            _ => panic!("Expected iterator"),
+        }
     }
 
-    /// Native Function
-    fn clock(_: usize, _: usize) -> Value {
-        let now = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap();
-        Value::Number(now.as_secs_f64())
+    /// Go back to the resumer of the running coroutine, which can't be resumed anymore
+    fn finish_coroutine(&mut self) {
+        self.close_upvalues(0);
+        let (resumer, coroutine) = self.resumers.pop().unwrap();
+        coroutine.borrow_mut().state = CoroutineState::Done;
+        self.restore_context(resumer);
     }
 
-    fn runtime_error(&self, message: &str) {
-        println!("{}", message);
-        for frame in self.frames.iter().rev() {
-            let function = &frame.function;
-            eprint!("[line {}] in ", function.chunk.lines[frame.ip]);
-            if function.name.len() == 0 {
-                eprintln!("script");
-            } else {
-                eprintln!("{}()", function.name);
+    /// Take the running stack and frames out of the VM so that another context can run
+    fn suspend_context(&mut self) -> ExecutionContext {
+        let stack = std::mem::take(&mut self.stack);
+        let upvalues = self
+            .open_upvalues
+            .drain(..)
+            .filter_map(|upvalue| {
+                let ObjUpvalue::Open(slot) = *upvalue.borrow() else {
+                    return None;
+                };
+                *upvalue.borrow_mut() = ObjUpvalue::Closed(stack[slot].clone());
+                Some((slot, upvalue))
+            })
+            .collect();
+        ExecutionContext {
+            stack,
+            frames: std::mem::take(&mut self.frames),
+            upvalues,
+        }
+    }
+
+    /// Make the suspended context the running one again
+    fn restore_context(&mut self, context: ExecutionContext) {
+        self.stack = context.stack;
+        self.frames = context.frames;
+        // variables which closures changed in the meantime move back to the stack
+        for (slot, upvalue) in context.upvalues {
+            if let ObjUpvalue::Closed(value) = &*upvalue.borrow() {
+                self.stack[slot] = value.clone();
             }
+            *upvalue.borrow_mut() = ObjUpvalue::Open(slot);
+            self.open_upvalues.push(upvalue);
+        }
+    }
+
+    fn define_native(
+        &mut self,
+        name: &str,
+        arity: RangeInclusive<usize>,
+        function: NativeFunction,
+    ) {
+        let obj_native = ObjNative::new(function, arity);
+        let native_function = Obj::NativeFunction(obj_native);
+        self.builtins
+            .insert(name.to_string(), Value::Obj(native_function));
+    }
+
+    fn runtime_error(&mut self, message: &str) {
+        eprintln!("{}", message);
+        for line in self.stack_trace() {
+            eprintln!("{line}");
         }
+        self.reset_stack();
+    }
+
+    /// The location in every active function including the resumers of coroutines, innermost first
+    fn stack_trace(&self) -> Vec<String> {
+        self.resumers
+            .iter()
+            .flat_map(|(resumer, _)| &resumer.frames)
+            .chain(&self.frames)
+            .rev()
+            .map(|frame| {
+                let function = &frame.closure.function;
+                // ip already points to the next instruction
+                let line = function.chunk.lines[frame.ip - 1];
+                if function.script && Rc::ptr_eq(&frame.closure.module, &self.main_module) {
+                    format!("[line {line}] in script")
+                } else if function.script {
+                    format!("[line {line}] in {}", frame.closure.module)
+                } else {
+                    format!("[line {line}] in {}()", function.name)
+                }
+            })
+            .collect()
+    }
+
+    fn reset_stack(&mut self) {
+        for (_, coroutine) in self.resumers.drain(..) {
+            coroutine.borrow_mut().state = CoroutineState::Done;
+        }
+        self.stack.clear();
+        self.frames.clear();
+        self.open_upvalues.clear();
+        // nothing is running, so the modules which didn't finish loading never will
+        let main_module = &self.main_module;
+        self.modules
+            .retain(|_, module| module.loaded.get() || Rc::ptr_eq(module, main_module));
     }
 }
 
@@ -305,67 +1152,672 @@ fn is_falsey(value: Value) -> bool {
     }
 }
 
-pub fn interpret(vm: &mut VM, source: &str) -> Result<(), InterpretError> {
-    let function = compile(source)?;
-
-    let frame = CallFrame::new(function, 0);
-    vm.frames.push(frame);
-    if let Err(err) = vm.run() {
-        match err {
-            InterpretError::RuntimeError(ref message) => {
-                vm.runtime_error(&message);
-                return Err(err);
-            }
-            _ => panic!("Not supposed to raise other than RuntimeError"),
-        }
+/// Bitwise operators only accept numbers without fractional part
+fn to_integer(number: f64) -> Result<i64, InterpretError> {
+    if number.fract() != 0.0 || number < i64::MIN as f64 || number >= i64::MAX as f64 {
+        let message = "Operands of bitwise operators must be integers.".to_string();
+        return Err(InterpretError::RuntimeError(message));
     }
-    Ok(())
+    Ok(number as i64)
 }
 
-#[derive(Debug)]
-pub enum InterpretError {
-    CompileError,
-    RuntimeError(String),
+fn integer_overflow() -> InterpretError {
+    InterpretError::RuntimeError("Integer overflow.".to_string())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    mod interpret {
-        use std::fs;
-
-        use super::*;
-
-        fn execute_file(path: &str) -> Result<(), InterpretError> {
-            let source = fs::read_to_string(path).unwrap();
-            let mut vm = VM::new();
-            interpret(&mut vm, &source)
+/// Arithmetic on two integers, which reports overflow instead of wrapping around
+fn integer_operation(left: i64, right: i64, operator: &OpCode) -> Result<Value, InterpretError> {
+    let result = match operator {
+        OpCode::OpAdd => left.checked_add(right),
+        OpCode::OpSubtract => left.checked_sub(right),
+        OpCode::OpMultiply => left.checked_mul(right),
+        OpCode::OpDivide | OpCode::OpModulo if right == 0 => {
+            let message = "Division by zero.".to_string();
+            return Err(InterpretError::RuntimeError(message));
         }
+        OpCode::OpDivide => left.checked_div(right),
+        OpCode::OpModulo => left.checked_rem(right),
+        // a negative exponent can't give an integer result
+        OpCode::OpPower if right < 0 => {
+            return Ok(Value::Number((left as f64).powf(right as f64)));
+        }
+        OpCode::OpPower => u32::try_from(right)
+            .ok()
+            .and_then(|exponent| left.checked_pow(exponent)),
+        OpCode::OpBitAnd
+        | OpCode::OpBitOr
+        | OpCode::OpBitXor
+        | OpCode::OpShiftLeft
+        | OpCode::OpShiftRight => Some(bitwise_operation(left, right, operator)?),
+        OpCode::OpGreater => return Ok(Value::Bool(left > right)),
+        OpCode::OpLess => return Ok(Value::Bool(left < right)),
+        _ => panic!("We got {operator:?}."),
+    };
+    result.map(Value::Integer).ok_or_else(integer_overflow)
+}
 
-        #[test]
-        fn test_logical_ops() {
-            assert!(execute_file("samples/logical_ops.lox").is_ok())
+fn bitwise_operation(left: i64, right: i64, operator: &OpCode) -> Result<i64, InterpretError> {
+    let result = match operator {
+        OpCode::OpBitAnd => left & right,
+        OpCode::OpBitOr => left | right,
+        OpCode::OpBitXor => left ^ right,
+        OpCode::OpShiftLeft | OpCode::OpShiftRight => {
+            if !(0..64).contains(&right) {
+                let message = format!("Shift amount must be between 0 and 63 but got {right}.");
+                return Err(InterpretError::RuntimeError(message));
+            }
+            if *operator == OpCode::OpShiftLeft {
+                left << right
+            } else {
+                left >> right
+            }
         }
+        _ => panic!("We got {operator:?}."),
+    };
+    Ok(result)
+}
 
-        #[test]
-        fn test_variables() {
-            assert!(execute_file("samples/variables.lox").is_ok())
+/// Errors expose their `message` and `stack_trace`
+fn error_property(error: &ObjError, name: &str) -> Result<Value, InterpretError> {
+    match name {
+        "message" => Ok(Value::LString(error.message.clone())),
+        "stack_trace" => {
+            let lines = error
+                .stack_trace
+                .iter()
+                .map(|line| Value::LString(line.clone()))
+                .collect();
+            let list = ObjList::new(lines);
+            Ok(Value::Obj(Obj::List(Rc::new(RefCell::new(list)))))
         }
+        _ => {
+            let message = format!("Undefined property '{name}'.");
+            Err(InterpretError::RuntimeError(message))
+        }
+    }
+}
 
-        #[test]
-        fn test_self_reference_variable() {
-            assert!(execute_file("samples/self_reference_variable.lox").is_err())
+fn module_export(module: &ObjModule, name: &str) -> Result<Value, InterpretError> {
+    match module.globals.borrow().get(name) {
+        Some(value) => Ok(value.clone()),
+        None => {
+            let message = format!("Module '{}' has no export '{name}'.", module.name());
+            Err(InterpretError::RuntimeError(message))
         }
+    }
+}
 
-        #[test]
-        fn test_if_statement() {
-            assert!(execute_file("samples/if_statement.lox").is_ok())
+/// Coroutines expose whether they are `done` and the `resume` method, which is also called `next`
+fn coroutine_property(
+    coroutine: &Rc<RefCell<ObjCoroutine>>,
+    name: &str,
+) -> Result<Value, InterpretError> {
+    match name {
+        "done" => Ok(Value::Bool(coroutine.borrow().is_done())),
+        "resume" | "next" => Ok(Value::Obj(Obj::Resume(coroutine.clone()))),
+        _ => {
+            let message = format!("Undefined property '{name}'.");
+            Err(InterpretError::RuntimeError(message))
         }
+    }
+}
 
-        #[test]
-        fn test_loops() {
-            assert!(execute_file("samples/loops.lox").is_ok())
+/// Start iterating over the value in a for-in loop.
+/// Coroutines and instances with a `next` method are iterators by themselves.
+fn get_iterator(value: Value) -> Result<Value, InterpretError> {
+    let iterator = match &value {
+        Value::Obj(Obj::List(list)) => ObjIterator::List {
+            list: list.clone(),
+            index: 0,
+        },
+        Value::LString(string) => ObjIterator::Chars {
+            string: string.clone(),
+            offset: 0,
+        },
+        Value::Obj(Obj::Map(map)) => {
+            let keys: Vec<Value> = map
+                .borrow()
+                .sorted_entries()
+                .into_iter()
+                .map(|(key, _)| key.to_value())
+                .collect();
+            ObjIterator::Keys(keys.into_iter())
+        }
+        Value::Obj(Obj::Range(range)) => ObjIterator::Range {
+            next: range.start,
+            end: range.end,
+            step: range.step,
+        },
+        Value::Obj(Obj::Coroutine(_) | Obj::Instance(_)) => return Ok(value),
+        _ => {
+            let message =
+                "Only lists, maps, strings, ranges, coroutines and instances can be iterated."
+                    .to_string();
+            return Err(InterpretError::RuntimeError(message));
+        }
+    };
+    Ok(Value::Obj(Obj::Iterator(Rc::new(RefCell::new(iterator)))))
+}
+
+/// `target[index]`
+fn get_index(target: &Value, index: &Value) -> Result<Value, InterpretError> {
+    match target {
+        Value::Obj(Obj::List(list)) => {
+            let list = list.borrow();
+            let index = sequence_index(index, list.items.len())?;
+            Ok(list.items[index].clone())
+        }
+        Value::Obj(Obj::Map(map)) => match map.borrow().entries.get(&map_key(index)?) {
+            Some(value) => Ok(value.clone()),
+            None => {
+                let message = format!("Key '{index}' is not in the map.");
+                Err(InterpretError::RuntimeError(message))
+            }
+        },
+        Value::Obj(Obj::Tuple(tuple)) => {
+            let index = sequence_index(index, tuple.items.len())?;
+            Ok(tuple.items[index].clone())
+        }
+        // strings are indexed by characters rather than bytes
+        Value::LString(string) => {
+            let index = sequence_index(index, string.chars().count())?;
+            Ok(Value::LString(
+                string.chars().nth(index).unwrap().to_string(),
+            ))
+        }
+        _ => {
+            let message = "Only lists, maps, tuples and strings can be indexed.".to_string();
+            Err(InterpretError::RuntimeError(message))
+        }
+    }
+}
+
+/// `target[start:end]`, where a nil bound means the start or the end of the target
+fn get_slice(target: &Value, start: &Value, end: &Value) -> Result<Value, InterpretError> {
+    match target {
+        Value::Obj(Obj::List(list)) => {
+            let items = &list.borrow().items;
+            let (start, end) = slice_bounds(start, end, items.len())?;
+            let list = ObjList::new(items[start..end].to_vec());
+            Ok(Value::Obj(Obj::List(Rc::new(RefCell::new(list)))))
+        }
+        Value::LString(string) => {
+            let (start, end) = slice_bounds(start, end, string.chars().count())?;
+            let slice = string.chars().skip(start).take(end - start).collect();
+            Ok(Value::LString(slice))
+        }
+        _ => {
+            let message = "Only lists and strings can be sliced.".to_string();
+            Err(InterpretError::RuntimeError(message))
+        }
+    }
+}
+
+/// `target[index] = value`
+fn set_index(target: &Value, index: &Value, value: Value) -> Result<(), InterpretError> {
+    match target {
+        Value::Obj(Obj::List(list)) => {
+            let mut list = list.borrow_mut();
+            let index = sequence_index(index, list.items.len())?;
+            list.items[index] = value;
+            Ok(())
+        }
+        Value::Obj(Obj::Map(map)) => {
+            map.borrow_mut().entries.insert(map_key(index)?, value);
+            Ok(())
+        }
+        Value::LString(_) => {
+            let message = "Strings are immutable.".to_string();
+            Err(InterpretError::RuntimeError(message))
+        }
+        Value::Obj(Obj::Tuple(_)) => {
+            let message = "Tuples are immutable.".to_string();
+            Err(InterpretError::RuntimeError(message))
+        }
+        _ => {
+            let message = "Only lists and maps can be indexed.".to_string();
+            Err(InterpretError::RuntimeError(message))
+        }
+    }
+}
+
+fn map_key(key: &Value) -> Result<MapKey, InterpretError> {
+    MapKey::from_value(key).ok_or_else(|| {
+        let message = "Map keys must be strings, numbers, booleans or nil.".to_string();
+        InterpretError::RuntimeError(message)
+    })
+}
+
+/// Convert the index value into a position in a list or a string of the given length
+fn sequence_index(index: &Value, length: usize) -> Result<usize, InterpretError> {
+    let Some(index) = index.as_integer() else {
+        let message = "Index must be an integer.".to_string();
+        return Err(InterpretError::RuntimeError(message));
+    };
+    if index < 0 {
+        let message = format!("Index {index} is negative.");
+        return Err(InterpretError::RuntimeError(message));
+    }
+    if index as usize >= length {
+        let message = format!("Index {index} is out of bounds for length {length}.");
+        return Err(InterpretError::RuntimeError(message));
+    }
+    Ok(index as usize)
+}
+
+/// Convert a bound of a slice into a position, which may point just past the last item
+fn slice_bound(bound: &Value, length: usize) -> Result<usize, InterpretError> {
+    let Some(bound) = bound.as_integer() else {
+        let message = "Slice bound must be an integer.".to_string();
+        return Err(InterpretError::RuntimeError(message));
+    };
+    if bound < 0 {
+        let message = format!("Slice bound {bound} is negative.");
+        return Err(InterpretError::RuntimeError(message));
+    }
+    if bound as usize > length {
+        let message = format!("Slice bound {bound} is out of bounds for length {length}.");
+        return Err(InterpretError::RuntimeError(message));
+    }
+    Ok(bound as usize)
+}
+
+/// Convert the bounds of a slice into positions in a list or a string of the given length
+fn slice_bounds(
+    start: &Value,
+    end: &Value,
+    length: usize,
+) -> Result<(usize, usize), InterpretError> {
+    let start = match start {
+        Value::Nil => 0,
+        _ => slice_bound(start, length)?,
+    };
+    let end = match end {
+        Value::Nil => length,
+        _ => slice_bound(end, length)?,
+    };
+    if start > end {
+        let message = format!("Slice start {start} is after its end {end}.");
+        return Err(InterpretError::RuntimeError(message));
+    }
+    Ok((start, end))
+}
+
+pub fn interpret(vm: &mut VM, source: &str) -> Result<(), InterpretError> {
+    let function = compile(source, vm.strip_asserts)?;
+
+    let closure = ObjClosure::new(Rc::new(function), Vec::new(), vm.main_module.clone());
+    let frame = CallFrame::new(Rc::new(closure), 0, 0);
+    vm.frames.push(frame);
+    if let Err(err) = vm.run() {
+        match err {
+            InterpretError::RuntimeError(ref message) => {
+                vm.runtime_error(message);
+                return Err(err);
+            }
+            _ => panic!("Not supposed to raise other than RuntimeError"),
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum InterpretError {
+    CompileError,
+    RuntimeError(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod interpret {
+        use std::fs;
+
+        use super::*;
+
+        fn execute_file(path: &str) -> Result<(), InterpretError> {
+            let source = fs::read_to_string(path).unwrap();
+            let mut vm = VM::new();
+            vm.set_main_path(Path::new(path));
+            interpret(&mut vm, &source)
+        }
+
+        #[test]
+        fn test_logical_ops() {
+            assert!(execute_file("samples/logical_ops.lox").is_ok())
+        }
+
+        #[test]
+        fn test_variables() {
+            assert!(execute_file("samples/variables.lox").is_ok())
+        }
+
+        #[test]
+        fn test_self_reference_variable() {
+            assert!(execute_file("samples/self_reference_variable.lox").is_err())
+        }
+
+        #[test]
+        fn test_if_statement() {
+            assert!(execute_file("samples/if_statement.lox").is_ok())
+        }
+
+        #[test]
+        fn test_loops() {
+            assert!(execute_file("samples/loops.lox").is_ok())
+        }
+
+        #[test]
+        fn test_break_continue() {
+            assert!(execute_file("samples/break_continue.lox").is_ok())
+        }
+
+        #[test]
+        fn test_break_outside_loop() {
+            assert!(execute_file("samples/break_outside_loop.lox").is_err())
+        }
+
+        #[test]
+        fn test_string() {
+            assert!(execute_file("samples/string.lox").is_ok())
+        }
+
+        #[test]
+        fn test_invalid_escape() {
+            assert!(execute_file("samples/invalid_escape.lox").is_err())
+        }
+
+        #[test]
+        fn test_operators() {
+            assert!(execute_file("samples/operators.lox").is_ok())
+        }
+
+        #[test]
+        fn test_bitwise_non_integer() {
+            assert!(execute_file("samples/bitwise_non_integer.lox").is_err())
+        }
+
+        #[test]
+        fn test_compound_assignment() {
+            assert!(execute_file("samples/compound_assignment.lox").is_ok())
+        }
+
+        #[test]
+        fn test_invalid_compound_assignment() {
+            assert!(execute_file("samples/invalid_compound_assignment.lox").is_err())
+        }
+
+        #[test]
+        fn test_prefix_increment_index() {
+            assert!(execute_file("samples/prefix_increment_index.lox").is_err())
+        }
+
+        #[test]
+        fn test_prefix_increment_property() {
+            assert!(execute_file("samples/prefix_increment_property.lox").is_err())
+        }
+
+        #[test]
+        fn test_conditional() {
+            assert!(execute_file("samples/conditional.lox").is_ok())
+        }
+
+        #[test]
+        fn test_conditional_missing_colon() {
+            assert!(execute_file("samples/conditional_missing_colon.lox").is_err())
+        }
+
+        #[test]
+        fn test_lambda() {
+            assert!(execute_file("samples/lambda.lox").is_ok())
+        }
+
+        #[test]
+        fn test_lambda_invalid_parameter() {
+            assert!(execute_file("samples/lambda_invalid_parameter.lox").is_err())
+        }
+
+        #[test]
+        fn test_default_parameters() {
+            assert!(execute_file("samples/default_parameters.lox").is_ok())
+        }
+
+        #[test]
+        fn test_default_parameters_arity() {
+            assert!(execute_file("samples/default_parameters_arity.lox").is_err())
+        }
+
+        #[test]
+        fn test_required_after_default() {
+            assert!(execute_file("samples/required_after_default.lox").is_err())
+        }
+
+        #[test]
+        fn test_exceptions() {
+            assert!(execute_file("samples/exceptions.lox").is_ok())
+        }
+
+        #[test]
+        fn test_uncaught_exception() {
+            assert!(execute_file("samples/uncaught_exception.lox").is_err())
+        }
+
+        #[test]
+        fn test_break_out_of_finally() {
+            assert!(execute_file("samples/break_out_of_finally.lox").is_ok())
+        }
+
+        #[test]
+        fn test_finally_early_exit() {
+            assert!(execute_file("samples/finally_early_exit.lox").is_ok())
+        }
+
+        #[test]
+        fn test_import() {
+            assert!(execute_file("samples/import.lox").is_ok())
+        }
+
+        #[test]
+        fn test_import_cycle() {
+            assert!(execute_file("samples/import_cycle.lox").is_err())
+        }
+
+        #[test]
+        fn test_import_throwing_twice() {
+            assert!(execute_file("samples/import_throwing_twice.lox").is_ok())
+        }
+
+        #[test]
+        fn test_import_missing() {
+            assert!(execute_file("samples/import_missing.lox").is_err())
+        }
+
+        #[test]
+        fn test_const() {
+            assert!(execute_file("samples/const.lox").is_ok())
+        }
+
+        #[test]
+        fn test_const_local_assignment() {
+            assert!(execute_file("samples/const_local_assignment.lox").is_err())
+        }
+
+        #[test]
+        fn test_const_upvalue_assignment() {
+            assert!(execute_file("samples/const_upvalue_assignment.lox").is_err())
+        }
+
+        #[test]
+        fn test_const_global_assignment() {
+            assert!(execute_file("samples/const_global_assignment.lox").is_err())
+        }
+
+        #[test]
+        fn test_const_without_initializer() {
+            assert!(execute_file("samples/const_without_initializer.lox").is_err())
+        }
+
+        #[test]
+        fn test_integers() {
+            assert!(execute_file("samples/integers.lox").is_ok())
+        }
+
+        #[test]
+        fn test_large_integer_equality() {
+            assert!(execute_file("samples/large_integer_equality.lox").is_ok())
+        }
+
+        #[test]
+        fn test_integer_division_by_zero() {
+            assert!(execute_file("samples/integer_division_by_zero.lox").is_err())
+        }
+
+        #[test]
+        fn test_integer_overflow() {
+            assert!(execute_file("samples/integer_overflow.lox").is_err())
+        }
+
+        #[test]
+        fn test_integer_literal_too_large() {
+            assert!(execute_file("samples/integer_literal_too_large.lox").is_err())
+        }
+
+        #[test]
+        fn test_block_comment() {
+            assert!(execute_file("samples/block_comment.lox").is_ok())
+        }
+
+        #[test]
+        fn test_unterminated_block_comment() {
+            assert!(execute_file("samples/unterminated_block_comment.lox").is_err())
+        }
+
+        #[test]
+        fn test_strings() {
+            assert!(execute_file("samples/strings.lox").is_ok())
+        }
+
+        #[test]
+        fn test_string_index_out_of_bounds() {
+            assert!(execute_file("samples/string_index_out_of_bounds.lox").is_err())
+        }
+
+        #[test]
+        fn test_undefined_string_method() {
+            assert!(execute_file("samples/undefined_string_method.lox").is_err())
+        }
+
+        #[test]
+        fn test_generators() {
+            assert!(execute_file("samples/generators.lox").is_ok())
+        }
+
+        #[test]
+        fn test_resume_finished_coroutine() {
+            assert!(execute_file("samples/resume_finished_coroutine.lox").is_err())
+        }
+
+        #[test]
+        fn test_yield_outside_function() {
+            assert!(execute_file("samples/yield_outside_function.lox").is_err())
+        }
+
+        #[test]
+        fn test_uncaught_in_coroutine() {
+            assert!(execute_file("samples/uncaught_in_coroutine.lox").is_err())
+        }
+
+        #[test]
+        fn test_recursive_generator() {
+            match execute_file("samples/recursive_generator.lox") {
+                Err(InterpretError::RuntimeError(message)) => {
+                    assert_eq!(message, "Stack overflow.")
+                }
+                _ => panic!("Expected a stack overflow"),
+            }
+        }
+
+        #[test]
+        fn test_for_in() {
+            assert!(execute_file("samples/for_in.lox").is_ok())
+        }
+
+        #[test]
+        fn test_for_in_not_iterable() {
+            assert!(execute_file("samples/for_in_not_iterable.lox").is_err())
+        }
+
+        #[test]
+        fn test_tuples() {
+            assert!(execute_file("samples/tuples.lox").is_ok())
+        }
+
+        #[test]
+        fn test_unpack_arity_mismatch() {
+            assert!(execute_file("samples/unpack_arity_mismatch.lox").is_err())
+        }
+
+        #[test]
+        fn test_unpack_non_tuple() {
+            assert!(execute_file("samples/unpack_non_tuple.lox").is_err())
+        }
+
+        #[test]
+        fn test_unpack_own_initializer() {
+            assert!(execute_file("samples/unpack_own_initializer.lox").is_err())
+        }
+
+        #[test]
+        fn test_match() {
+            assert!(execute_file("samples/match.lox").is_ok())
+        }
+
+        #[test]
+        fn test_match_case_after_default() {
+            assert!(execute_file("samples/match_case_after_default.lox").is_err())
+        }
+
+        #[test]
+        fn test_nil_safe() {
+            assert!(execute_file("samples/nil_safe.lox").is_ok())
+        }
+
+        #[test]
+        fn test_optional_call_non_function() {
+            assert!(execute_file("samples/optional_call_non_function.lox").is_err())
+        }
+
+        #[test]
+        fn test_assert() {
+            assert!(execute_file("samples/assert.lox").is_ok())
+        }
+
+        #[test]
+        fn test_assert_failure() {
+            assert!(execute_file("samples/assert_failure.lox").is_err())
+        }
+
+        #[test]
+        fn test_strip_asserts() {
+            let source = fs::read_to_string("samples/assert_failure.lox").unwrap();
+            let mut vm = VM::new();
+            vm.set_strip_asserts(true);
+            // the list is indexed out of bounds instead
+            match interpret(&mut vm, &source) {
+                Err(InterpretError::RuntimeError(message)) => {
+                    assert_eq!(message, "Index 5 is out of bounds for length 3.")
+                }
+                _ => panic!("Expected runtime error"),
+            }
+        }
+
+        #[test]
+        fn test_strip_yielding_assert() {
+            let source = fs::read_to_string("samples/assert_yield.lox").unwrap();
+            let mut vm = VM::new();
+            vm.set_strip_asserts(true);
+            assert!(interpret(&mut vm, &source).is_ok())
         }
 
         #[test]
@@ -377,6 +1829,118 @@ mod tests {
         fn test_closure() {
             assert!(execute_file("samples/closure.lox").is_ok())
         }
+
+        #[test]
+        fn test_counter() {
+            assert!(execute_file("samples/counter.lox").is_ok())
+        }
+
+        #[test]
+        fn test_class() {
+            assert!(execute_file("samples/class.lox").is_ok())
+        }
+
+        #[test]
+        fn test_method() {
+            assert!(execute_file("samples/method.lox").is_ok())
+        }
+
+        #[test]
+        fn test_initializer_arity() {
+            assert!(execute_file("samples/initializer_arity.lox").is_err())
+        }
+
+        #[test]
+        fn test_return_value_from_initializer() {
+            assert!(execute_file("samples/return_value_from_initializer.lox").is_err())
+        }
+
+        #[test]
+        fn test_inheritance() {
+            assert!(execute_file("samples/inheritance.lox").is_ok())
+        }
+
+        #[test]
+        fn test_inherit_from_non_class() {
+            assert!(execute_file("samples/inherit_from_non_class.lox").is_err())
+        }
+
+        #[test]
+        fn test_inherit_from_itself() {
+            assert!(execute_file("samples/inherit_from_itself.lox").is_err())
+        }
+
+        #[test]
+        fn test_list() {
+            assert!(execute_file("samples/list.lox").is_ok())
+        }
+
+        #[test]
+        fn test_list_cycle() {
+            assert!(execute_file("samples/list_cycle.lox").is_ok())
+        }
+
+        #[test]
+        fn test_list_negative_index() {
+            assert!(execute_file("samples/list_negative_index.lox").is_err())
+        }
+
+        #[test]
+        fn test_list_out_of_bounds() {
+            assert!(execute_file("samples/list_out_of_bounds.lox").is_err())
+        }
+
+        #[test]
+        fn test_map() {
+            assert!(execute_file("samples/map.lox").is_ok())
+        }
+
+        #[test]
+        fn test_map_cycle() {
+            assert!(execute_file("samples/map_cycle.lox").is_ok())
+        }
+
+        #[test]
+        fn test_map_missing_key() {
+            assert!(execute_file("samples/map_missing_key.lox").is_err())
+        }
+
+        #[test]
+        fn test_map_unhashable_key() {
+            assert!(execute_file("samples/map_unhashable_key.lox").is_err())
+        }
+
+        #[test]
+        fn test_undefined_property() {
+            assert!(execute_file("samples/undefined_property.lox").is_err())
+        }
+    }
+
+    #[test]
+    fn test_sequence_index() {
+        assert_eq!(sequence_index(&Value::Integer(2), 3).unwrap(), 2);
+        assert_eq!(sequence_index(&Value::Number(2.0), 3).unwrap(), 2);
+        assert!(sequence_index(&Value::Number(3.0), 3).is_err());
+        assert!(sequence_index(&Value::Number(-1.0), 3).is_err());
+        assert!(sequence_index(&Value::Number(0.5), 3).is_err());
+        assert!(sequence_index(&Value::Nil, 3).is_err());
+    }
+
+    #[test]
+    fn test_slice_bounds() {
+        let bounds = slice_bounds(&Value::Integer(1), &Value::Nil, 3).unwrap();
+        assert_eq!(bounds, (1, 3));
+        let bounds = slice_bounds(&Value::Nil, &Value::Integer(0), 3).unwrap();
+        assert_eq!(bounds, (0, 0));
+        let bounds = slice_bounds(&Value::Integer(3), &Value::Integer(3), 3).unwrap();
+        assert_eq!(bounds, (3, 3));
+        match slice_bounds(&Value::Integer(0), &Value::Integer(10), 3) {
+            Err(InterpretError::RuntimeError(message)) => {
+                assert_eq!(message, "Slice bound 10 is out of bounds for length 3.")
+            }
+            _ => panic!("Expected the end to be out of bounds"),
+        }
+        assert!(slice_bounds(&Value::Integer(2), &Value::Integer(1), 3).is_err());
     }
 
     #[test]
@@ -435,6 +1999,67 @@ mod tests {
             assert_eq!(stack[0].as_number(), 3.0);
         }
 
+        #[test]
+        fn test_modulo() {
+            let mut stack = vec![Value::Number(7.0), Value::Number(3.0)];
+            VM::binary_operation(&mut stack, &OpCode::OpModulo).unwrap();
+            assert_eq!(stack[0].as_number(), 1.0);
+        }
+
+        #[test]
+        fn test_power() {
+            let mut stack = vec![Value::Number(2.0), Value::Number(10.0)];
+            VM::binary_operation(&mut stack, &OpCode::OpPower).unwrap();
+            assert_eq!(stack[0].as_number(), 1024.0);
+        }
+
+        #[test]
+        fn test_bitwise() {
+            let mut stack = vec![Value::Number(6.0), Value::Number(3.0)];
+            VM::binary_operation(&mut stack, &OpCode::OpBitXor).unwrap();
+            assert_eq!(stack[0].as_number(), 5.0);
+            let mut stack = vec![Value::Number(1.0), Value::Number(4.0)];
+            VM::binary_operation(&mut stack, &OpCode::OpShiftLeft).unwrap();
+            assert_eq!(stack[0].as_number(), 16.0);
+        }
+
+        #[test]
+        fn test_bitwise_non_integer() {
+            let mut stack = vec![Value::Number(1.5), Value::Number(1.0)];
+            assert!(VM::binary_operation(&mut stack, &OpCode::OpBitAnd).is_err());
+            let mut stack = vec![Value::Number(1.0), Value::Number(64.0)];
+            assert!(VM::binary_operation(&mut stack, &OpCode::OpShiftRight).is_err());
+        }
+
+        #[test]
+        fn test_integer() {
+            let mut stack = vec![Value::Integer(7), Value::Integer(2)];
+            VM::binary_operation(&mut stack, &OpCode::OpDivide).unwrap();
+            assert!(matches!(stack[0], Value::Integer(3)));
+            let mut stack = vec![Value::Integer(2), Value::Integer(-1)];
+            VM::binary_operation(&mut stack, &OpCode::OpPower).unwrap();
+            assert_eq!(stack[0].as_number(), 0.5);
+        }
+
+        #[test]
+        fn test_integer_and_float() {
+            let mut stack = vec![Value::Integer(1), Value::Number(0.5)];
+            VM::binary_operation(&mut stack, &OpCode::OpAdd).unwrap();
+            assert!(matches!(stack[0], Value::Number(number) if number == 1.5));
+        }
+
+        #[test]
+        fn test_integer_errors() {
+            let mut stack = vec![Value::Integer(1), Value::Integer(0)];
+            assert!(VM::binary_operation(&mut stack, &OpCode::OpModulo).is_err());
+            let mut stack = vec![Value::Integer(i64::MAX), Value::Integer(1)];
+            assert!(VM::binary_operation(&mut stack, &OpCode::OpAdd).is_err());
+            let mut stack = vec![Value::Integer(i64::MIN), Value::Integer(-1)];
+            assert!(VM::binary_operation(&mut stack, &OpCode::OpDivide).is_err());
+            let mut stack = vec![Value::Integer(10), Value::Integer(19)];
+            assert!(VM::binary_operation(&mut stack, &OpCode::OpPower).is_err());
+        }
+
         #[test]
         #[should_panic(expected = "We got OpReturn.")]
         fn test_invalid_opcode() {